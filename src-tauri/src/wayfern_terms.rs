@@ -1,8 +1,175 @@
 use directories::BaseDirs;
-use std::path::PathBuf;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 const MIN_VALID_TIMESTAMP: i64 = 1577836800; // 2020-01-01 00:00:00 UTC
 
+/// Bump this whenever the Wayfern terms and conditions text changes in a way
+/// that requires users to re-accept, and update [`CURRENT_TERMS_TEXT`] to match.
+const CURRENT_TERMS_VERSION: u32 = 1;
+
+/// The exact terms and conditions text shipped with this build. `terms_hash`
+/// in the acceptance record is a SHA-256 of this text, so a build that ships
+/// different wording (even at the same `CURRENT_TERMS_VERSION`, e.g. a typo
+/// fix that wasn't version-bumped) is still detected as a mismatch.
+const CURRENT_TERMS_TEXT: &str = include_str!("../resources/wayfern_terms.txt");
+
+/// Application-embedded key used to HMAC-sign the on-disk acceptance record.
+///
+/// This only raises the bar against casual tampering (e.g. editing the file by
+/// hand); it is not a secret in the cryptographic sense since it ships in the
+/// binary.
+const LICENSE_HMAC_KEY: &[u8] = b"Wayfern-Terms-Acceptance-Record-v1";
+
+/// How long [`WayfernTermsManager::validate_license`] keeps returning `Ok`
+/// after the validation server last confirmed the record, if that server is
+/// unreachable. Mirrors the grace window a license daemon grants before it
+/// hard-fails a renewal check.
+const VALIDATION_LEEWAY_SECS: i64 = 72 * 60 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn current_terms_hash() -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(CURRENT_TERMS_TEXT.as_bytes());
+  hex::encode(hasher.finalize())
+}
+
+fn now_unix() -> Result<i64, String> {
+  std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.as_secs() as i64)
+    .map_err(|e| format!("Failed to get current timestamp: {e}"))
+}
+
+/// The structured, signed acceptance record written to disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LicenseRecord {
+  accepted_at: i64,
+  terms_version: u32,
+  terms_hash: String,
+  machine_id: String,
+  /// When acceptance expires and must be re-validated. `None` means
+  /// acceptance never expires (the common, non-enterprise case).
+  #[serde(default)]
+  expires_at: Option<i64>,
+  /// The last time [`WayfernTermsManager::validate_license`] confirmed this
+  /// record with the validation server.
+  #[serde(default)]
+  last_validated_at: Option<i64>,
+}
+
+/// Response from the license validation endpoint.
+#[derive(Debug, Deserialize)]
+struct ValidationResponse {
+  valid: bool,
+  #[serde(default)]
+  expires_at: Option<i64>,
+}
+
+/// Writes `contents` to `path` durably and atomically: the data is written to
+/// a sibling temp file, fsynced, then renamed over `path` so a crash or power
+/// loss can never leave a truncated or partially-written file behind. Used
+/// by this module to write the license record.
+fn write_file_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+  let parent = path
+    .parent()
+    .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no parent"))?;
+
+  let tmp_path = parent.join(format!(
+    ".{}.tmp-{}",
+    path
+      .file_name()
+      .and_then(|n| n.to_str())
+      .unwrap_or("state"),
+    std::process::id()
+  ));
+
+  {
+    let mut tmp_file = std::fs::File::create(&tmp_path)?;
+    tmp_file.write_all(contents)?;
+    tmp_file.flush()?;
+    tmp_file.sync_all()?;
+  }
+
+  #[cfg(unix)]
+  {
+    std::fs::rename(&tmp_path, path)?;
+    // fsync the directory entry so the rename itself survives a crash.
+    if let Ok(dir) = std::fs::File::open(parent) {
+      let _ = dir.sync_all();
+    }
+  }
+
+  #[cfg(windows)]
+  {
+    replace_file_windows(&tmp_path, path)?;
+  }
+
+  Ok(())
+}
+
+/// On Windows, `std::fs::rename` is not guaranteed atomic when the
+/// destination already exists, so we go through `ReplaceFile`/`MoveFileExW`
+/// instead, which are.
+#[cfg(windows)]
+fn replace_file_windows(from: &Path, to: &Path) -> std::io::Result<()> {
+  use std::os::windows::ffi::OsStrExt;
+
+  fn to_wide(path: &Path) -> Vec<u16> {
+    path
+      .as_os_str()
+      .encode_wide()
+      .chain(std::iter::once(0))
+      .collect()
+  }
+
+  if to.exists() {
+    let from_w = to_wide(from);
+    let to_w = to_wide(to);
+
+    let result = unsafe { ReplaceFileW(to_w.as_ptr(), from_w.as_ptr(), std::ptr::null(), 0, std::ptr::null_mut(), std::ptr::null_mut()) };
+    if result == 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+  } else {
+    let from_w = to_wide(from);
+    let to_w = to_wide(to);
+    const MOVEFILE_REPLACE_EXISTING: u32 = 0x1;
+    const MOVEFILE_WRITE_THROUGH: u32 = 0x8;
+
+    let result = unsafe {
+      MoveFileExW(
+        from_w.as_ptr(),
+        to_w.as_ptr(),
+        MOVEFILE_REPLACE_EXISTING | MOVEFILE_WRITE_THROUGH,
+      )
+    };
+    if result == 0 {
+      return Err(std::io::Error::last_os_error());
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(windows)]
+extern "system" {
+  fn ReplaceFileW(
+    lp_replaced_file_name: *const u16,
+    lp_replacement_file_name: *const u16,
+    lp_backup_file_name: *const u16,
+    dw_replace_flags: u32,
+    lp_exclude: *mut std::ffi::c_void,
+    lp_reserved: *mut std::ffi::c_void,
+  ) -> i32;
+
+  fn MoveFileExW(lp_existing_file_name: *const u16, lp_new_file_name: *const u16, dw_flags: u32) -> i32;
+}
+
 pub struct WayfernTermsManager {
   base_dirs: BaseDirs,
 }
@@ -67,6 +234,42 @@ impl WayfernTermsManager {
     }
   }
 
+  /// Best-effort, stable identifier for the current host, used to bind an
+  /// acceptance record to the machine it was written on.
+  fn machine_id(&self) -> String {
+    machine_uid::get().unwrap_or_else(|_| "unknown-machine".to_string())
+  }
+
+  fn sign_payload(&self, payload: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(LICENSE_HMAC_KEY)
+      .expect("HMAC can take a key of any size");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+  }
+
+  /// Verifies `payload` against `expected_mac_hex` in constant time.
+  fn verify_payload(&self, payload: &str, expected_mac_hex: &str) -> bool {
+    let Ok(expected_mac) = hex::decode(expected_mac_hex) else {
+      return false;
+    };
+
+    let mut mac = match HmacSha256::new_from_slice(LICENSE_HMAC_KEY) {
+      Ok(mac) => mac,
+      Err(_) => return false,
+    };
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&expected_mac).is_ok()
+  }
+
+  /// Returns the parsed record if `record_json` verifies against `mac_hex`
+  /// and is in the current JSON format.
+  fn parse_record(&self, record_json: &str, mac_hex: &str) -> Option<LicenseRecord> {
+    if !self.verify_payload(record_json, mac_hex) {
+      return None;
+    }
+    serde_json::from_str(record_json).ok()
+  }
+
   pub fn is_terms_accepted(&self) -> bool {
     let license_file = self.get_license_file_path();
 
@@ -74,39 +277,81 @@ impl WayfernTermsManager {
       return false;
     }
 
-    // Read the timestamp from the file
     let contents = match std::fs::read_to_string(&license_file) {
       Ok(c) => c,
       Err(_) => return false,
     };
+    let contents = contents.trim();
 
-    // Parse timestamp (Wayfern stores Unix timestamp as text)
-    let timestamp: i64 = match contents.trim().parse() {
-      Ok(t) => t,
-      Err(_) => return false,
-    };
-
-    // Check that timestamp is positive and after 2020-01-01
-    timestamp >= MIN_VALID_TIMESTAMP
+    match contents.split_once('\n') {
+      Some((first_line, mac_hex)) => {
+        if let Some(record) = self.parse_record(first_line, mac_hex.trim()) {
+          // Re-prompt when either the terms version or the hash of the
+          // shipped terms text differs from this build's.
+          let terms_current =
+            record.terms_version == CURRENT_TERMS_VERSION && record.terms_hash == current_terms_hash();
+          let not_expired = match record.expires_at {
+            Some(expires_at) => now_unix().map(|now| now <= expires_at).unwrap_or(false),
+            None => true,
+          };
+          // Binds acceptance to the machine it was written on, so a record
+          // copied from another install (same embedded HMAC key everywhere)
+          // doesn't verify as accepted here.
+          let same_machine = record.machine_id == self.machine_id();
+          terms_current && not_expired && same_machine
+        } else {
+          // Pre-versioning signed format: "timestamp|terms_version|machine_id".
+          self.verify_payload(first_line, mac_hex.trim())
+            && first_line.split('|').next_back() == Some(self.machine_id().as_str())
+        }
+      }
+      None => {
+        // Legacy plaintext-timestamp file, predating signing: treat as
+        // acceptance of terms version 1.
+        match contents.parse::<i64>() {
+          Ok(timestamp) => timestamp >= MIN_VALID_TIMESTAMP && CURRENT_TERMS_VERSION == 1,
+          Err(_) => false,
+        }
+      }
+    }
   }
 
-  pub async fn accept_terms(&self) -> Result<(), String> {
+  /// Signs and atomically writes `record` to the license file.
+  fn write_record(&self, record: &LicenseRecord) -> Result<(), String> {
     let license_file = self.get_license_file_path();
 
-    // Create the parent directory if it doesn't exist
     if let Some(parent) = license_file.parent() {
       std::fs::create_dir_all(parent)
         .map_err(|e| format!("Failed to create license directory: {e}"))?;
     }
 
-    // Write the current timestamp to the license file
-    let timestamp = std::time::SystemTime::now()
-      .duration_since(std::time::UNIX_EPOCH)
-      .map_err(|e| format!("Failed to get current timestamp: {e}"))?
-      .as_secs();
+    let record_json =
+      serde_json::to_string(record).map_err(|e| format!("Failed to serialize license record: {e}"))?;
+    let mac_hex = self.sign_payload(&record_json);
+    let contents = format!("{record_json}\n{mac_hex}");
+
+    write_file_atomic(&license_file, contents.as_bytes())
+      .map_err(|e| format!("Failed to write license file: {e}"))
+  }
+
+  /// Reads back the current, signature-verified [`LicenseRecord`], if any.
+  fn read_record(&self) -> Option<LicenseRecord> {
+    let contents = std::fs::read_to_string(self.get_license_file_path()).ok()?;
+    let contents = contents.trim();
+    let (record_json, mac_hex) = contents.split_once('\n')?;
+    self.parse_record(record_json, mac_hex.trim())
+  }
 
-    std::fs::write(&license_file, timestamp.to_string())
-      .map_err(|e| format!("Failed to write license file: {e}"))?;
+  pub async fn accept_terms(&self) -> Result<(), String> {
+    let record = LicenseRecord {
+      accepted_at: now_unix()?,
+      terms_version: CURRENT_TERMS_VERSION,
+      terms_hash: current_terms_hash(),
+      machine_id: self.machine_id(),
+      expires_at: None,
+      last_validated_at: None,
+    };
+    self.write_record(&record)?;
 
     // Verify the license file was created correctly
     if !self.is_terms_accepted() {
@@ -116,6 +361,66 @@ impl WayfernTermsManager {
     log::info!("Wayfern terms and conditions accepted successfully");
     Ok(())
   }
+
+  /// Re-validates the current acceptance record against `endpoint` (e.g. for
+  /// enterprise/commercial licensing that needs periodic server checks).
+  ///
+  /// On a successful reply with `valid: true`, bumps `last_validated_at` and,
+  /// if the server returned one, updates `expires_at`. The reply is plain
+  /// JSON; no signature on it is checked. If the server can't be reached (or
+  /// answers with an error status), this still returns `Ok` as long as the
+  /// record was last validated (or, if never validated, accepted) within
+  /// [`VALIDATION_LEEWAY_SECS`], so a transient network outage doesn't lock
+  /// users out. [`Self::is_terms_accepted`] does not look at
+  /// `last_validated_at`; only this function's result reflects the leeway.
+  pub async fn validate_license(&self, endpoint: &str) -> Result<(), String> {
+    let mut record = self
+      .read_record()
+      .ok_or("No signed license record to validate")?;
+
+    let now = now_unix()?;
+    let client = reqwest::Client::builder()
+      .user_agent("DonutBrowser")
+      .timeout(std::time::Duration::from_secs(10))
+      .build()
+      .map_err(|e| format!("Failed to build HTTP client: {e}"))?;
+
+    let request_body = serde_json::json!({
+      "machine_id": record.machine_id,
+      "terms_version": record.terms_version,
+    });
+
+    match client.post(endpoint).json(&request_body).send().await {
+      Ok(response) if response.status().is_success() => {
+        let validation: ValidationResponse = response
+          .json()
+          .await
+          .map_err(|e| format!("Invalid validation response: {e}"))?;
+
+        if !validation.valid {
+          return Err("License validation server rejected this machine".to_string());
+        }
+
+        record.last_validated_at = Some(now);
+        if validation.expires_at.is_some() {
+          record.expires_at = validation.expires_at;
+        }
+        self.write_record(&record)
+      }
+      _ => {
+        let last_validated = record.last_validated_at.unwrap_or(record.accepted_at);
+        if now - last_validated <= VALIDATION_LEEWAY_SECS {
+          log::warn!(
+            "License validation server unreachable; within {}s offline leeway, continuing",
+            VALIDATION_LEEWAY_SECS
+          );
+          Ok(())
+        } else {
+          Err("License validation server unreachable and offline leeway exceeded".to_string())
+        }
+      }
+    }
+  }
 }
 
 lazy_static::lazy_static! {
@@ -161,4 +466,132 @@ mod tests {
     // The actual behavior depends on whether the file exists
     let _ = manager.is_terms_accepted();
   }
+
+  #[test]
+  fn test_write_file_atomic_roundtrip() {
+    let dir = std::env::temp_dir().join(format!("wayfern-atomic-write-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("state");
+
+    write_file_atomic(&path, b"hello").unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+
+    // Writing again should replace the contents, not append or corrupt them.
+    write_file_atomic(&path, b"world!").unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "world!");
+
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[test]
+  fn test_sign_and_verify_payload_roundtrip() {
+    let manager = WayfernTermsManager::new();
+    // Canonical (legacy, pipe-delimited) payload shape: "<ts>|<ver>|<machine>".
+    let payload = format!("{}|{}|{}", 1700000000, CURRENT_TERMS_VERSION, manager.machine_id());
+    let mac_hex = manager.sign_payload(&payload);
+    assert!(manager.verify_payload(&payload, &mac_hex));
+    assert!(!manager.verify_payload(&payload, "deadbeef"));
+  }
+
+  #[test]
+  fn test_license_record_roundtrip() {
+    let manager = WayfernTermsManager::new();
+    let record = LicenseRecord {
+      accepted_at: 1700000000,
+      terms_version: CURRENT_TERMS_VERSION,
+      terms_hash: current_terms_hash(),
+      machine_id: manager.machine_id(),
+      expires_at: None,
+      last_validated_at: None,
+    };
+    let record_json = serde_json::to_string(&record).unwrap();
+    let mac_hex = manager.sign_payload(&record_json);
+
+    let parsed = manager.parse_record(&record_json, &mac_hex).unwrap();
+    assert_eq!(parsed.terms_version, CURRENT_TERMS_VERSION);
+    assert_eq!(parsed.terms_hash, current_terms_hash());
+  }
+
+  #[test]
+  fn test_license_record_with_stale_terms_hash_is_detected() {
+    let manager = WayfernTermsManager::new();
+    let record = LicenseRecord {
+      accepted_at: 1700000000,
+      terms_version: CURRENT_TERMS_VERSION,
+      terms_hash: "stale-hash-from-an-older-build".to_string(),
+      machine_id: manager.machine_id(),
+      expires_at: None,
+      last_validated_at: None,
+    };
+    let record_json = serde_json::to_string(&record).unwrap();
+    let mac_hex = manager.sign_payload(&record_json);
+
+    let parsed = manager.parse_record(&record_json, &mac_hex).unwrap();
+    assert_ne!(parsed.terms_hash, current_terms_hash());
+  }
+
+  #[test]
+  fn test_record_without_expiry_fields_defaults_to_none() {
+    // expires_at/last_validated_at must default to None when deserializing
+    // records written before this field existed.
+    let old_record_json =
+      r#"{"accepted_at":1700000000,"terms_version":1,"terms_hash":"abc","machine_id":"m"}"#;
+    let record: LicenseRecord = serde_json::from_str(old_record_json).unwrap();
+    assert_eq!(record.expires_at, None);
+    assert_eq!(record.last_validated_at, None);
+  }
+
+  #[test]
+  fn test_offline_leeway_window() {
+    let now = 1_000_000_i64;
+    let last_validated = now - (VALIDATION_LEEWAY_SECS - 1);
+    assert!(now - last_validated <= VALIDATION_LEEWAY_SECS);
+
+    let stale_last_validated = now - (VALIDATION_LEEWAY_SECS + 1);
+    assert!(now - stale_last_validated > VALIDATION_LEEWAY_SECS);
+  }
+
+  #[test]
+  fn test_license_record_from_another_machine_is_detected() {
+    let manager = WayfernTermsManager::new();
+    let record = LicenseRecord {
+      accepted_at: 1700000000,
+      terms_version: CURRENT_TERMS_VERSION,
+      terms_hash: current_terms_hash(),
+      machine_id: "some-other-machine".to_string(),
+      expires_at: None,
+      last_validated_at: None,
+    };
+    let record_json = serde_json::to_string(&record).unwrap();
+    let mac_hex = manager.sign_payload(&record_json);
+
+    // The MAC still verifies (the key is embedded and identical on every
+    // install), but the record was written on a different machine.
+    let parsed = manager.parse_record(&record_json, &mac_hex).unwrap();
+    assert_ne!(parsed.machine_id, manager.machine_id());
+  }
+
+  #[test]
+  fn test_legacy_payload_from_another_machine_is_detected() {
+    let manager = WayfernTermsManager::new();
+    let foreign_payload = format!("1700000000|{}|some-other-machine", CURRENT_TERMS_VERSION);
+    let mac_hex = manager.sign_payload(&foreign_payload);
+
+    // The MAC still verifies, but the embedded machine_id doesn't match.
+    assert!(manager.verify_payload(&foreign_payload, &mac_hex));
+    assert_ne!(
+      foreign_payload.split('|').next_back(),
+      Some(manager.machine_id().as_str())
+    );
+  }
+
+  #[test]
+  fn test_legacy_plaintext_timestamp_is_valid_but_unsigned() {
+    let manager = WayfernTermsManager::new();
+    // A legacy file is a single line with no MAC; split_once('\n') returns None for it.
+    let legacy = "1700000000";
+    assert!(legacy.parse::<i64>().unwrap() >= MIN_VALID_TIMESTAMP);
+    assert!(legacy.split_once('\n').is_none());
+    let _ = manager;
+  }
 }
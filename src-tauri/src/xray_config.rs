@@ -1,9 +1,19 @@
-use base64::{engine::general_purpose::STANDARD, Engine};
+use base64::{engine::general_purpose::STANDARD, DecodeError, Engine};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use url::Url;
 
+/// Decodes `input` as base64, first normalizing a URL-safe alphabet
+/// (`-`/`_`) to the standard one (`+`/`/`). Many proxy links and
+/// subscription blobs are URL-safe base64 so they round-trip through a URL
+/// without escaping; decoding them with the standard alphabet's `-` mapping
+/// alone silently corrupts every byte that encoded a `_`.
+fn decode_lenient_base64(input: &str) -> Result<Vec<u8>, DecodeError> {
+  let normalized = input.replace('-', "+").replace('_', "/");
+  STANDARD.decode(normalized)
+}
+
 /// Parsed proxy configuration from URL
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParsedProxy {
@@ -13,6 +23,299 @@ pub struct ParsedProxy {
   pub outbound: Value,
 }
 
+impl ParsedProxy {
+  /// Reconstructs the canonical shareable URL this proxy was (or could have
+  /// been) parsed from, the inverse of [`parse_proxy_url`]. Round-tripping
+  /// `parse_proxy_url(proxy.to_url()?, tag)` yields an equivalent outbound.
+  pub fn to_url(&self) -> Result<String, String> {
+    match self.protocol.as_str() {
+      "vmess" => self.vmess_to_url(),
+      "vless" => self.vless_to_url(),
+      "trojan" => self.trojan_to_url(),
+      "shadowsocks" => self.shadowsocks_to_url(),
+      other => Err(format!("Cannot serialize proxy protocol '{other}' back to a URL")),
+    }
+  }
+
+  fn vmess_to_url(&self) -> Result<String, String> {
+    let vnext = &self.outbound["settings"]["vnext"][0];
+    let address = vnext["address"].as_str().ok_or("vmess outbound missing address")?;
+    let port = vnext["port"].as_u64().ok_or("vmess outbound missing port")?;
+
+    let user = &vnext["users"][0];
+    let id = user["id"].as_str().ok_or("vmess outbound missing user id")?;
+    let aid = user["alterId"].as_u64().unwrap_or(0);
+    let security = user["security"].as_str().unwrap_or("auto");
+
+    let stream = &self.outbound["streamSettings"];
+    let net = stream["network"].as_str().unwrap_or("tcp");
+    let tls = stream["security"].as_str().unwrap_or("none");
+
+    let (host, path) = match net {
+      "ws" => (
+        stream["wsSettings"]["headers"]["Host"].as_str().unwrap_or(""),
+        stream["wsSettings"]["path"].as_str().unwrap_or(""),
+      ),
+      "grpc" => ("", stream["grpcSettings"]["serviceName"].as_str().unwrap_or("")),
+      "h2" => (
+        stream["httpSettings"]["host"]
+          .as_array()
+          .and_then(|hosts| hosts.first())
+          .and_then(|h| h.as_str())
+          .unwrap_or(""),
+        stream["httpSettings"]["path"].as_str().unwrap_or(""),
+      ),
+      "kcp" => ("", stream["kcpSettings"]["seed"].as_str().unwrap_or("")),
+      "quic" => (
+        stream["quicSettings"]["security"].as_str().unwrap_or(""),
+        stream["quicSettings"]["key"].as_str().unwrap_or(""),
+      ),
+      _ => ("", ""),
+    };
+
+    let sni = stream["tlsSettings"]["serverName"].as_str().unwrap_or("");
+    let alpn = stream["tlsSettings"]["alpn"].as_array().map(|values| {
+      values
+        .iter()
+        .filter_map(|v| v.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+    });
+
+    let mut vmess_json = serde_json::Map::new();
+    vmess_json.insert("v".to_string(), json!("2"));
+    vmess_json.insert("ps".to_string(), json!(self.remark.clone().unwrap_or_default()));
+    vmess_json.insert("add".to_string(), json!(address));
+    vmess_json.insert("port".to_string(), json!(port));
+    vmess_json.insert("id".to_string(), json!(id));
+    vmess_json.insert("aid".to_string(), json!(aid));
+    vmess_json.insert("scy".to_string(), json!(security));
+    vmess_json.insert("net".to_string(), json!(net));
+    vmess_json.insert("type".to_string(), json!("none"));
+    vmess_json.insert("host".to_string(), json!(host));
+    vmess_json.insert("path".to_string(), json!(path));
+    vmess_json.insert("tls".to_string(), json!(tls));
+    if !sni.is_empty() {
+      vmess_json.insert("sni".to_string(), json!(sni));
+    }
+    if let Some(alpn) = alpn {
+      vmess_json.insert("alpn".to_string(), json!(alpn));
+    }
+
+    let json_str = serde_json::to_string(&vmess_json)
+      .map_err(|e| format!("Failed to serialize vmess config: {e}"))?;
+    Ok(format!("vmess://{}", STANDARD.encode(json_str)))
+  }
+
+  fn vless_to_url(&self) -> Result<String, String> {
+    let vnext = &self.outbound["settings"]["vnext"][0];
+    let address = vnext["address"].as_str().ok_or("vless outbound missing address")?;
+    let port = vnext["port"].as_u64().ok_or("vless outbound missing port")? as u16;
+
+    let user = &vnext["users"][0];
+    let uuid = user["id"].as_str().ok_or("vless outbound missing user id")?;
+    let encryption = user["encryption"].as_str().unwrap_or("none");
+    let flow = user["flow"].as_str().unwrap_or("");
+
+    let stream = &self.outbound["streamSettings"];
+    let net = stream["network"].as_str().unwrap_or("tcp");
+    let security = stream["security"].as_str().unwrap_or("none");
+
+    let mut params = vec![
+      ("encryption".to_string(), encryption.to_string()),
+      ("type".to_string(), net.to_string()),
+      ("security".to_string(), security.to_string()),
+      ("flow".to_string(), flow.to_string()),
+    ];
+
+    match net {
+      "ws" => {
+        params.push(("path".to_string(), stream["wsSettings"]["path"].as_str().unwrap_or("/").to_string()));
+        params.push((
+          "host".to_string(),
+          stream["wsSettings"]["headers"]["Host"].as_str().unwrap_or("").to_string(),
+        ));
+      }
+      "grpc" => {
+        params.push((
+          "serviceName".to_string(),
+          stream["grpcSettings"]["serviceName"].as_str().unwrap_or("").to_string(),
+        ));
+      }
+      "xhttp" => {
+        params.push((
+          "path".to_string(),
+          stream["xhttpSettings"]["path"].as_str().unwrap_or("/").to_string(),
+        ));
+        params.push((
+          "host".to_string(),
+          stream["xhttpSettings"]["host"].as_str().unwrap_or("").to_string(),
+        ));
+        params.push((
+          "mode".to_string(),
+          stream["xhttpSettings"]["mode"].as_str().unwrap_or("stream-up").to_string(),
+        ));
+      }
+      "kcp" => {
+        params.push((
+          "headerType".to_string(),
+          stream["kcpSettings"]["header"]["type"].as_str().unwrap_or("none").to_string(),
+        ));
+        params.push(("seed".to_string(), stream["kcpSettings"]["seed"].as_str().unwrap_or("").to_string()));
+      }
+      "h2" => {
+        params.push(("path".to_string(), stream["httpSettings"]["path"].as_str().unwrap_or("/").to_string()));
+        let hosts = stream["httpSettings"]["host"]
+          .as_array()
+          .map(|values| {
+            values
+              .iter()
+              .filter_map(|v| v.as_str())
+              .collect::<Vec<_>>()
+              .join(",")
+          })
+          .unwrap_or_default();
+        params.push(("host".to_string(), hosts));
+      }
+      _ => {}
+    }
+
+    match security {
+      "tls" => {
+        params.push((
+          "sni".to_string(),
+          stream["tlsSettings"]["serverName"].as_str().unwrap_or("").to_string(),
+        ));
+        params.push((
+          "fp".to_string(),
+          stream["tlsSettings"]["fingerprint"].as_str().unwrap_or("chrome").to_string(),
+        ));
+        if let Some(alpn) = stream["tlsSettings"]["alpn"].as_array() {
+          let alpn_str = alpn.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>().join(",");
+          params.push(("alpn".to_string(), alpn_str));
+        }
+      }
+      "reality" => {
+        params.push((
+          "sni".to_string(),
+          stream["realitySettings"]["serverName"].as_str().unwrap_or("").to_string(),
+        ));
+        params.push((
+          "fp".to_string(),
+          stream["realitySettings"]["fingerprint"].as_str().unwrap_or("chrome").to_string(),
+        ));
+        params.push((
+          "pbk".to_string(),
+          stream["realitySettings"]["publicKey"].as_str().unwrap_or("").to_string(),
+        ));
+        params.push((
+          "sid".to_string(),
+          stream["realitySettings"]["shortId"].as_str().unwrap_or("").to_string(),
+        ));
+        params.push((
+          "spx".to_string(),
+          stream["realitySettings"]["spiderX"].as_str().unwrap_or("").to_string(),
+        ));
+      }
+      _ => {}
+    }
+
+    build_proxy_share_url("vless", uuid, address, port, &params, self.remark.as_deref())
+  }
+
+  fn trojan_to_url(&self) -> Result<String, String> {
+    let server = &self.outbound["settings"]["servers"][0];
+    let address = server["address"].as_str().ok_or("trojan outbound missing address")?;
+    let port = server["port"].as_u64().ok_or("trojan outbound missing port")? as u16;
+    let password = server["password"].as_str().ok_or("trojan outbound missing password")?;
+
+    let stream = &self.outbound["streamSettings"];
+    let net = stream["network"].as_str().unwrap_or("tcp");
+    let security = stream["security"].as_str().unwrap_or("tls");
+
+    let mut params = vec![
+      ("type".to_string(), net.to_string()),
+      ("security".to_string(), security.to_string()),
+      (
+        "sni".to_string(),
+        stream["tlsSettings"]["serverName"].as_str().unwrap_or("").to_string(),
+      ),
+    ];
+
+    match net {
+      "ws" => {
+        params.push(("path".to_string(), stream["wsSettings"]["path"].as_str().unwrap_or("/").to_string()));
+        params.push((
+          "host".to_string(),
+          stream["wsSettings"]["headers"]["Host"].as_str().unwrap_or("").to_string(),
+        ));
+      }
+      "grpc" => {
+        params.push((
+          "serviceName".to_string(),
+          stream["grpcSettings"]["serviceName"].as_str().unwrap_or("").to_string(),
+        ));
+      }
+      _ => {}
+    }
+
+    build_proxy_share_url("trojan", password, address, port, &params, self.remark.as_deref())
+  }
+
+  fn shadowsocks_to_url(&self) -> Result<String, String> {
+    let server = &self.outbound["settings"]["servers"][0];
+    let address = server["address"].as_str().ok_or("shadowsocks outbound missing address")?;
+    let port = server["port"].as_u64().ok_or("shadowsocks outbound missing port")?;
+    let method = server["method"].as_str().ok_or("shadowsocks outbound missing method")?;
+    let password = server["password"].as_str().ok_or("shadowsocks outbound missing password")?;
+
+    let userinfo = STANDARD.encode(format!("{method}:{password}"));
+    let mut result = format!("ss://{userinfo}@{address}:{port}");
+    if let Some(remark) = self.remark.as_deref().filter(|r| !r.is_empty()) {
+      result.push('#');
+      result.push_str(&urlencoding::encode(remark));
+    }
+    Ok(result)
+  }
+}
+
+/// Builds a `scheme://userinfo@host:port?query#fragment` share link, using
+/// `url::Url` to validate and assemble the authority and `urlencoding` to
+/// percent-encode query values and the fragment.
+fn build_proxy_share_url(
+  scheme: &str,
+  userinfo: &str,
+  host: &str,
+  port: u16,
+  params: &[(String, String)],
+  remark: Option<&str>,
+) -> Result<String, String> {
+  let mut url = Url::parse(&format!("{scheme}://{host}:{port}"))
+    .map_err(|e| format!("Failed to build {scheme} URL: {e}"))?;
+  url
+    .set_username(userinfo)
+    .map_err(|_| format!("Invalid {scheme} userinfo"))?;
+
+  let mut result = url.to_string();
+
+  let query: Vec<String> = params
+    .iter()
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(key, value)| format!("{key}={}", urlencoding::encode(value)))
+    .collect();
+  if !query.is_empty() {
+    result.push('?');
+    result.push_str(&query.join("&"));
+  }
+
+  if let Some(remark) = remark.filter(|r| !r.is_empty()) {
+    result.push('#');
+    result.push_str(&urlencoding::encode(remark));
+  }
+
+  Ok(result)
+}
+
 /// Parse VMess URL (vmess://base64json)
 fn parse_vmess(url_str: &str, tag: &str) -> Result<ParsedProxy, String> {
   let base64_str = url_str
@@ -20,9 +323,8 @@ fn parse_vmess(url_str: &str, tag: &str) -> Result<ParsedProxy, String> {
     .ok_or("Invalid vmess URL")?;
 
   // Decode base64
-  let decoded = STANDARD
-    .decode(base64_str.replace(['-', '_'], "+"))
-    .map_err(|e| format!("Failed to decode vmess base64: {}", e))?;
+  let decoded =
+    decode_lenient_base64(base64_str).map_err(|e| format!("Failed to decode vmess base64: {}", e))?;
 
   let config_str =
     String::from_utf8(decoded).map_err(|e| format!("Invalid UTF-8 in vmess config: {}", e))?;
@@ -406,9 +708,8 @@ fn parse_shadowsocks(url_str: &str, tag: &str) -> Result<ParsedProxy, String> {
       (user_parts[0].to_string(), user_parts[1].to_string())
     } else {
       // Base64 encoded
-      let decoded = STANDARD
-        .decode(user_part.replace(['-', '_'], "+"))
-        .map_err(|e| format!("Failed to decode ss base64: {}", e))?;
+      let decoded =
+        decode_lenient_base64(user_part).map_err(|e| format!("Failed to decode ss base64: {}", e))?;
       let decoded_str = String::from_utf8(decoded)
         .map_err(|e| format!("Invalid UTF-8 in ss user part: {}", e))?;
       let user_parts: Vec<&str> = decoded_str.splitn(2, ':').collect();
@@ -442,9 +743,8 @@ fn parse_shadowsocks(url_str: &str, tag: &str) -> Result<ParsedProxy, String> {
     (method, password, host, port)
   } else {
     // Legacy format: entire thing is base64 encoded
-    let decoded = STANDARD
-      .decode(raw.replace(['-', '_'], "+"))
-      .map_err(|e| format!("Failed to decode ss base64: {}", e))?;
+    let decoded =
+      decode_lenient_base64(raw).map_err(|e| format!("Failed to decode ss base64: {}", e))?;
     let decoded_str =
       String::from_utf8(decoded).map_err(|e| format!("Invalid UTF-8 in ss config: {}", e))?;
 
@@ -724,33 +1024,287 @@ pub fn is_xray_protocol(url: &str) -> bool {
     || url.starts_with("ss://")
 }
 
-/// Generate Xray config JSON for a proxy
-pub fn generate_xray_config(
-  main_proxy_url: &str,
-  local_port: u16,
-  pre_proxy_url: Option<&str>,
-) -> Result<Value, String> {
-  let mut outbounds = Vec::new();
+/// Decodes a subscription payload (the common format used by proxy
+/// providers) into its individual proxy URLs, one per line.
+///
+/// The payload is either a base64-encoded blob or already-plaintext
+/// newline-separated URLs; either is detected automatically. Each non-blank,
+/// non-comment line is parsed independently with [`parse_proxy_url`] using
+/// an auto-generated unique tag (`sub_0`, `sub_1`, ...), so a malformed
+/// entry only fails its own line rather than the whole subscription.
+pub fn parse_subscription(content: &str) -> Vec<Result<ParsedProxy, String>> {
+  let content = content.trim();
+
+  let decoded = decode_lenient_base64(content)
+    .ok()
+    .and_then(|bytes| String::from_utf8(bytes).ok())
+    .unwrap_or_else(|| content.to_string());
+
+  decoded
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("//"))
+    .enumerate()
+    .map(|(index, line)| parse_proxy_url(line, &format!("sub_{index}")))
+    .collect()
+}
+
+/// Maps a host pattern to the proxy URL that requests to matching hosts
+/// should use, so different sites can flow through different proxies.
+#[derive(Debug, Clone)]
+pub struct HostRoute {
+  pub pattern: String,
+  pub proxy_url: String,
+}
+
+impl HostRoute {
+  pub fn new(pattern: impl Into<String>, proxy_url: impl Into<String>) -> Self {
+    Self {
+      pattern: pattern.into(),
+      proxy_url: proxy_url.into(),
+    }
+  }
+
+  /// Whether `host` matches this route's pattern: a glob (containing `*`,
+  /// `?`, or `[]`) is compiled and matched as such, otherwise the pattern is
+  /// compared as an exact hostname.
+  pub fn matches(&self, host: &str) -> bool {
+    if is_glob_host_pattern(&self.pattern) {
+      glob::Pattern::new(&self.pattern)
+        .map(|compiled| compiled.matches(host))
+        .unwrap_or(false)
+    } else {
+      self.pattern.eq_ignore_ascii_case(host)
+    }
+  }
+}
+
+fn is_glob_host_pattern(pattern: &str) -> bool {
+  pattern.chars().any(|c| matches!(c, '*' | '?' | '[' | ']'))
+}
+
+/// Renders a host pattern as an Xray routing `domain` matcher: an exact
+/// hostname becomes `full:host`, a `*.`-suffix glob becomes `domain:suffix`
+/// (Xray's native domain-and-subdomains match), and any other glob is
+/// translated to `regexp:`.
+fn host_pattern_to_xray_domain_matcher(pattern: &str) -> String {
+  if !is_glob_host_pattern(pattern) {
+    return format!("full:{pattern}");
+  }
+
+  if let Some(suffix) = pattern.strip_prefix("*.") {
+    if !is_glob_host_pattern(suffix) {
+      return format!("domain:{suffix}");
+    }
+  }
+
+  format!("regexp:{}", glob_to_regex(pattern))
+}
+
+/// Minimal glob-to-regex translator covering `*` and `?`; good enough for
+/// host patterns, which don't need bracket-class support.
+fn glob_to_regex(pattern: &str) -> String {
+  let mut regex = String::from("^");
+  for c in pattern.chars() {
+    match c {
+      '*' => regex.push_str(".*"),
+      '?' => regex.push('.'),
+      '.' => regex.push_str("\\."),
+      other => regex.push(other),
+    }
+  }
+  regex.push('$');
+  regex
+}
+
+/// Well-known hosts that almost always belong on the direct route rather than
+/// tunneled through the proxy. Only applied when a caller opts in.
+const DEFAULT_BYPASS_RULES: &[&str] = &["localhost", "127.0.0.0/8", "::1", "fc00::/7"];
+
+/// A single classified bypass-list entry.
+enum BypassTarget {
+  /// A hostname or domain suffix (from a bare `example.com`, `.example.com`
+  /// or `*.example.com`), rendered as an Xray `domain:` rule, which matches
+  /// the domain and all its subdomains; a bare hostname is not matched
+  /// exactly.
+  Domain(String),
+  /// A literal IP or CIDR block, rendered as an Xray `ip` rule.
+  Ip(String),
+}
+
+/// Classifies a single bypass-list rule the way `reqwest`'s `NO_PROXY`
+/// parsing does: a literal IP/CIDR goes to the `ip` rule, everything else
+/// (bare hostname, leading-dot or `*.` wildcard suffix) goes to the `domain`
+/// rule.
+fn classify_bypass_rule(rule: &str) -> Option<BypassTarget> {
+  let rule = rule.trim();
+  if rule.is_empty() {
+    return None;
+  }
+
+  if rule.parse::<std::net::IpAddr>().is_ok() || rule.parse::<ipnet::IpNet>().is_ok() {
+    return Some(BypassTarget::Ip(rule.to_string()));
+  }
+
+  let host = rule
+    .strip_prefix("*.")
+    .or_else(|| rule.strip_prefix('.'))
+    .unwrap_or(rule);
+  Some(BypassTarget::Domain(host.to_string()))
+}
+
+/// Builds the routing rules that send bypass-list matches to the `direct`
+/// freedom outbound, ahead of the proxy catch-all.
+fn build_bypass_routing_rules(bypass_rules: &[String], include_default_bypass_rules: bool) -> Vec<Value> {
+  let mut domains = Vec::new();
+  let mut ips = Vec::new();
+
+  let defaults = include_default_bypass_rules
+    .then(|| DEFAULT_BYPASS_RULES.iter().map(|s| s.to_string()))
+    .into_iter()
+    .flatten();
+
+  for rule in defaults.chain(bypass_rules.iter().cloned()) {
+    match classify_bypass_rule(&rule) {
+      Some(BypassTarget::Domain(host)) => domains.push(format!("domain:{host}")),
+      Some(BypassTarget::Ip(ip)) => ips.push(ip),
+      None => {}
+    }
+  }
+
+  let mut rules = Vec::new();
+  if !domains.is_empty() {
+    rules.push(json!({
+      "type": "field",
+      "domain": domains,
+      "outboundTag": "direct"
+    }));
+  }
+  if !ips.is_empty() {
+    rules.push(json!({
+      "type": "field",
+      "ip": ips,
+      "outboundTag": "direct"
+    }));
+  }
+  rules
+}
+
+/// Builds one routing rule per `site_routes` entry and one Xray outbound per
+/// distinct proxy URL, tagging outbounds `proxy_site_0`, `proxy_site_1`, ...
+/// in first-seen order and reusing a tag when the same proxy URL appears
+/// more than once.
+fn build_site_route_rules(
+  site_routes: &[HostRoute],
+  outbounds: &mut Vec<Value>,
+) -> Result<Vec<Value>, String> {
+  let mut tag_by_url: HashMap<&str, String> = HashMap::new();
+  let mut rules = Vec::new();
+
+  for route in site_routes {
+    let tag = match tag_by_url.get(route.proxy_url.as_str()) {
+      Some(tag) => tag.clone(),
+      None => {
+        let tag = format!("proxy_site_{}", tag_by_url.len());
+        let outbound = parse_proxy_url(&route.proxy_url, &tag)?;
+        outbounds.push(outbound.outbound);
+        tag_by_url.insert(route.proxy_url.as_str(), tag.clone());
+        tag
+      }
+    };
+
+    rules.push(json!({
+      "type": "field",
+      "domain": [host_pattern_to_xray_domain_matcher(&route.pattern)],
+      "outboundTag": tag
+    }));
+  }
+
+  Ok(rules)
+}
+
+/// Parses an ordered proxy chain into tagged outbounds, wiring each hop's
+/// `proxySettings.tag` to the hop before it so Xray dials out through the
+/// whole chain to reach that hop's own remote server. The last hop is tagged
+/// `proxy_main` and is the one routing rules should target, since it's the
+/// one that actually carries the user's traffic.
+///
+/// `proxy_chain` must be non-empty and must not repeat a URL; a chain that
+/// passes through the same proxy twice is rejected as a cycle.
+fn build_proxy_chain_outbounds(proxy_chain: &[&str]) -> Result<Vec<Value>, String> {
+  if proxy_chain.is_empty() {
+    return Err("Proxy chain must contain at least one hop".to_string());
+  }
+
+  let mut seen = std::collections::HashSet::new();
+  for hop_url in proxy_chain {
+    if !seen.insert(*hop_url) {
+      return Err(format!(
+        "Proxy chain contains a cycle: '{hop_url}' appears more than once"
+      ));
+    }
+  }
 
-  // Parse main proxy
-  let mut main_outbound = parse_proxy_url(main_proxy_url, "proxy_main")?;
+  let last_index = proxy_chain.len() - 1;
+  let mut outbounds = Vec::with_capacity(proxy_chain.len());
+  let mut previous_tag: Option<String> = None;
 
-  // If pre-proxy is specified, set up proxy chain
-  if let Some(pre_url) = pre_proxy_url {
-    if !pre_url.is_empty() {
-      let pre_outbound = parse_proxy_url(pre_url, "proxy_pre")?;
-      outbounds.push(pre_outbound.outbound);
+  for (i, hop_url) in proxy_chain.iter().enumerate() {
+    let tag = if i == last_index {
+      "proxy_main".to_string()
+    } else {
+      format!("proxy_hop_{i}")
+    };
 
-      // Add proxy chain setting to main outbound
-      if let Some(obj) = main_outbound.outbound.as_object_mut() {
-        obj.insert("proxySettings".to_string(), json!({ "tag": "proxy_pre" }));
+    let mut hop_outbound = parse_proxy_url(hop_url, &tag)?;
+    if let Some(prev_tag) = &previous_tag {
+      if let Some(obj) = hop_outbound.outbound.as_object_mut() {
+        obj.insert("proxySettings".to_string(), json!({ "tag": prev_tag }));
       }
     }
+
+    outbounds.push(hop_outbound.outbound);
+    previous_tag = Some(tag);
   }
 
-  outbounds.push(main_outbound.outbound);
+  Ok(outbounds)
+}
+
+/// Generate Xray config JSON for a proxy.
+///
+/// `proxy_chain` is an ordered, non-empty list of proxy URLs to route
+/// through in sequence (e.g. residential -> VPS -> exit); a single-element
+/// chain behaves exactly like one direct upstream proxy.
+///
+/// `bypass_rules` lists destinations that should skip the proxy entirely and
+/// go out directly: bare hostnames, `*.example.com`/`.example.com` domain
+/// suffixes, literal IPs, or CIDR blocks. Set `include_default_bypass_rules`
+/// to also bypass localhost and other well-known local ranges.
+///
+/// `site_routes` lists host pattern -> proxy URL mappings evaluated in
+/// insertion order ahead of the proxy chain's catch-all, so different sites
+/// can be routed through different upstream proxies simultaneously.
+pub fn generate_xray_config(
+  local_port: u16,
+  proxy_chain: &[&str],
+  bypass_rules: &[String],
+  include_default_bypass_rules: bool,
+  site_routes: &[HostRoute],
+) -> Result<Value, String> {
+  let mut outbounds = build_proxy_chain_outbounds(proxy_chain)?;
+
+  let site_route_rules = build_site_route_rules(site_routes, &mut outbounds)?;
+
   outbounds.push(json!({ "protocol": "freedom", "tag": "direct" }));
 
+  let mut routing_rules = build_bypass_routing_rules(bypass_rules, include_default_bypass_rules);
+  routing_rules.extend(site_route_rules);
+  routing_rules.push(json!({
+    "type": "field",
+    "outboundTag": "proxy_main",
+    "port": "0-65535"
+  }));
+
   let config = json!({
     "log": {
       "loglevel": "warning"
@@ -766,11 +1320,7 @@ pub fn generate_xray_config(
     "outbounds": outbounds,
     "routing": {
       "domainStrategy": "IPIfNonMatch",
-      "rules": [{
-        "type": "field",
-        "outboundTag": "proxy_main",
-        "port": "0-65535"
-      }]
+      "rules": routing_rules
     }
   });
 
@@ -815,10 +1365,281 @@ mod tests {
   #[test]
   fn test_generate_xray_config() {
     let url = "socks5://localhost:1080";
-    let config = generate_xray_config(url, 10808, None);
+    let config = generate_xray_config(10808, &[url], &[], false, &[]);
     assert!(config.is_ok());
     let cfg = config.unwrap();
     assert!(cfg.get("inbounds").is_some());
     assert!(cfg.get("outbounds").is_some());
   }
+
+  #[test]
+  fn test_bypass_rules_split_into_domain_and_ip_routing_rules() {
+    let url = "socks5://localhost:1080";
+    let bypass = vec![
+      "example.com".to_string(),
+      "*.internal.example".to_string(),
+      "10.0.0.0/8".to_string(),
+      "203.0.113.5".to_string(),
+    ];
+    let config = generate_xray_config(10808, &[url], &bypass, false, &[]).unwrap();
+    let rules = config["routing"]["rules"].as_array().unwrap();
+
+    // Two bypass rules (domain, ip) plus the proxy_main catch-all.
+    assert_eq!(rules.len(), 3);
+    assert_eq!(
+      rules[0]["domain"],
+      json!(["domain:example.com", "domain:internal.example"])
+    );
+    assert_eq!(rules[1]["ip"], json!(["10.0.0.0/8", "203.0.113.5"]));
+    assert_eq!(rules[2]["outboundTag"], json!("proxy_main"));
+  }
+
+  #[test]
+  fn test_default_bypass_rules_only_applied_when_requested() {
+    let url = "socks5://localhost:1080";
+
+    let without_defaults = generate_xray_config(10808, &[url], &[], false, &[]).unwrap();
+    assert_eq!(
+      without_defaults["routing"]["rules"].as_array().unwrap().len(),
+      1
+    );
+
+    let with_defaults = generate_xray_config(10808, &[url], &[], true, &[]).unwrap();
+    let rules = with_defaults["routing"]["rules"].as_array().unwrap();
+    assert!(rules.len() > 1);
+  }
+
+  #[test]
+  fn test_site_routes_dedup_outbounds_and_map_patterns_to_tags() {
+    let site_routes = vec![
+      HostRoute::new("example.com", "socks5://proxy-a:1080"),
+      HostRoute::new("*.example.org", "socks5://proxy-b:1080"),
+      HostRoute::new("other.example.com", "socks5://proxy-a:1080"),
+    ];
+    let config = generate_xray_config(
+      10808,
+      &["socks5://localhost:1080"],
+      &[],
+      false,
+      &site_routes,
+    )
+    .unwrap();
+
+    let outbounds = config["outbounds"].as_array().unwrap();
+    let site_outbound_tags: Vec<&str> = outbounds
+      .iter()
+      .filter_map(|o| o["tag"].as_str())
+      .filter(|tag| tag.starts_with("proxy_site_"))
+      .collect();
+    // Same proxy URL reused across two patterns should only produce one outbound.
+    assert_eq!(site_outbound_tags, vec!["proxy_site_0", "proxy_site_1"]);
+
+    let rules = config["routing"]["rules"].as_array().unwrap();
+    assert_eq!(rules[0]["domain"], json!(["full:example.com"]));
+    assert_eq!(rules[0]["outboundTag"], json!("proxy_site_0"));
+    assert_eq!(rules[1]["domain"], json!(["domain:example.org"]));
+    assert_eq!(rules[1]["outboundTag"], json!("proxy_site_1"));
+    assert_eq!(rules[2]["domain"], json!(["full:other.example.com"]));
+    assert_eq!(rules[2]["outboundTag"], json!("proxy_site_0"));
+    assert_eq!(rules[3]["outboundTag"], json!("proxy_main"));
+  }
+
+  #[test]
+  fn test_proxy_chain_wires_each_hop_through_the_previous_one() {
+    let hops = [
+      "socks5://residential:1080",
+      "socks5://vps:1080",
+      "socks5://exit:1080",
+    ];
+    let config = generate_xray_config(10808, &hops, &[], false, &[]).unwrap();
+    let outbounds = config["outbounds"].as_array().unwrap();
+
+    let by_tag = |tag: &str| outbounds.iter().find(|o| o["tag"] == json!(tag)).unwrap();
+
+    assert!(by_tag("proxy_hop_0").get("proxySettings").is_none());
+    assert_eq!(by_tag("proxy_hop_1")["proxySettings"]["tag"], json!("proxy_hop_0"));
+    assert_eq!(by_tag("proxy_main")["proxySettings"]["tag"], json!("proxy_hop_1"));
+
+    let rules = config["routing"]["rules"].as_array().unwrap();
+    assert_eq!(rules[0]["outboundTag"], json!("proxy_main"));
+  }
+
+  #[test]
+  fn test_single_hop_chain_matches_old_direct_proxy_behavior() {
+    let config = generate_xray_config(10808, &["socks5://localhost:1080"], &[], false, &[]).unwrap();
+    let outbounds = config["outbounds"].as_array().unwrap();
+    let main = outbounds.iter().find(|o| o["tag"] == json!("proxy_main")).unwrap();
+    assert!(main.get("proxySettings").is_none());
+  }
+
+  #[test]
+  fn test_empty_proxy_chain_is_rejected() {
+    let result = generate_xray_config(10808, &[], &[], false, &[]);
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_proxy_chain_with_repeated_hop_is_rejected_as_a_cycle() {
+    let hops = ["socks5://a:1080", "socks5://b:1080", "socks5://a:1080"];
+    let result = generate_xray_config(10808, &hops, &[], false, &[]);
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_host_route_matches_exact_and_glob() {
+    let exact = HostRoute::new("example.com", "socks5://proxy:1080");
+    assert!(exact.matches("example.com"));
+    assert!(!exact.matches("sub.example.com"));
+
+    let glob = HostRoute::new("*.example.com", "socks5://proxy:1080");
+    assert!(glob.matches("api.example.com"));
+    assert!(!glob.matches("example.com"));
+  }
+
+  #[test]
+  fn test_vless_round_trips_through_to_url() {
+    let url = "vless://uuid-123@example.com:443?type=ws&security=tls&path=/path&host=cdn.example.com&sni=example.com#MyVLESS";
+    let proxy = parse_proxy_url(url, "test").unwrap();
+    let rebuilt = proxy.to_url().unwrap();
+    let reparsed = parse_proxy_url(&rebuilt, "test").unwrap();
+
+    assert_eq!(reparsed.protocol, "vless");
+    assert_eq!(reparsed.remark, Some("MyVLESS".to_string()));
+    assert_eq!(reparsed.outbound, proxy.outbound);
+  }
+
+  #[test]
+  fn test_trojan_round_trips_through_to_url() {
+    let url = "trojan://password123@example.com:443?sni=example.com#MyTrojan";
+    let proxy = parse_proxy_url(url, "test").unwrap();
+    let rebuilt = proxy.to_url().unwrap();
+    let reparsed = parse_proxy_url(&rebuilt, "test").unwrap();
+
+    assert_eq!(reparsed.protocol, "trojan");
+    assert_eq!(reparsed.remark, Some("MyTrojan".to_string()));
+    assert_eq!(reparsed.outbound, proxy.outbound);
+  }
+
+  #[test]
+  fn test_shadowsocks_round_trips_through_to_url() {
+    let url = "ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ=@example.com:8388#MyProxy";
+    let proxy = parse_proxy_url(url, "test").unwrap();
+    let rebuilt = proxy.to_url().unwrap();
+    let reparsed = parse_proxy_url(&rebuilt, "test").unwrap();
+
+    assert_eq!(reparsed.protocol, "shadowsocks");
+    assert_eq!(reparsed.remark, Some("MyProxy".to_string()));
+    assert_eq!(reparsed.outbound, proxy.outbound);
+  }
+
+  #[test]
+  fn test_vmess_round_trips_through_to_url() {
+    let vmess_json = serde_json::json!({
+      "v": "2",
+      "ps": "MyVmess",
+      "add": "example.com",
+      "port": "443",
+      "id": "uuid-456",
+      "aid": "0",
+      "scy": "auto",
+      "net": "ws",
+      "type": "none",
+      "host": "cdn.example.com",
+      "path": "/ws",
+      "tls": "tls",
+      "sni": "example.com"
+    });
+    let encoded = STANDARD.encode(vmess_json.to_string());
+    let url = format!("vmess://{encoded}");
+
+    let proxy = parse_proxy_url(&url, "test").unwrap();
+    let rebuilt = proxy.to_url().unwrap();
+    let reparsed = parse_proxy_url(&rebuilt, "test").unwrap();
+
+    assert_eq!(reparsed.protocol, "vmess");
+    assert_eq!(reparsed.remark, Some("MyVmess".to_string()));
+    assert_eq!(reparsed.outbound, proxy.outbound);
+  }
+
+  #[test]
+  fn test_to_url_rejects_unsupported_protocol() {
+    let proxy = ParsedProxy {
+      protocol: "socks".to_string(),
+      tag: "test".to_string(),
+      remark: None,
+      outbound: json!({}),
+    };
+    assert!(proxy.to_url().is_err());
+  }
+
+  #[test]
+  fn test_parse_subscription_plaintext_with_comments_and_blank_lines() {
+    let content = "\
+# my subscription
+ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ=@example.com:8388#One
+
+vless://uuid@example.com:443?type=ws&security=tls#Two
+";
+    let results = parse_subscription(content);
+    assert_eq!(results.len(), 2);
+
+    let first = results[0].as_ref().unwrap();
+    assert_eq!(first.protocol, "shadowsocks");
+    assert_eq!(first.tag, "sub_0");
+
+    let second = results[1].as_ref().unwrap();
+    assert_eq!(second.protocol, "vless");
+    assert_eq!(second.tag, "sub_1");
+  }
+
+  #[test]
+  fn test_parse_subscription_decodes_base64_blob() {
+    let plaintext = "ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ=@example.com:8388#One\nvless://uuid@example.com:443?type=ws&security=tls#Two";
+    let blob = STANDARD.encode(plaintext);
+
+    let results = parse_subscription(&blob);
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_ok());
+  }
+
+  #[test]
+  fn test_decode_lenient_base64_handles_url_safe_alphabet() {
+    use base64::engine::general_purpose::URL_SAFE;
+
+    // Pick plaintext whose standard base64 encoding contains both `+` and
+    // `/`, so the URL-safe encoding is guaranteed to contain `-` and `_`.
+    let plaintext: &[u8] = &[0xfb, 0xff, 0xbf];
+    assert!(STANDARD.encode(plaintext).contains(['+', '/']));
+
+    let url_safe_encoded = URL_SAFE.encode(plaintext);
+    assert!(url_safe_encoded.contains(['-', '_']));
+
+    let decoded = decode_lenient_base64(&url_safe_encoded).unwrap();
+    assert_eq!(decoded, plaintext);
+  }
+
+  #[test]
+  fn test_parse_subscription_decodes_url_safe_base64_blob() {
+    use base64::engine::general_purpose::URL_SAFE;
+
+    let plaintext = "ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ=@example.com:8388#One\nvless://uuid@example.com:443?type=ws&security=tls#Two";
+    let blob = URL_SAFE.encode(plaintext);
+
+    let results = parse_subscription(&blob);
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_ok());
+  }
+
+  #[test]
+  fn test_parse_subscription_keeps_per_line_results_on_malformed_entry() {
+    let content = "ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ=@example.com:8388#One\nnot-a-valid-proxy-url\nvless://uuid@example.com:443?type=ws&security=tls#Two";
+
+    let results = parse_subscription(content);
+    assert_eq!(results.len(), 3);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    assert!(results[2].is_ok());
+  }
 }
@@ -1,6 +1,7 @@
 use crate::xray_config::{generate_xray_config, is_xray_protocol};
 use directories::BaseDirs;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
@@ -29,13 +30,142 @@ pub struct XrayInstance {
   pub pid: u32,
   pub local_port: u16,
   pub upstream_url: String,
+  pub pre_proxy_url: Option<String>,
   pub config_path: PathBuf,
+  /// Snapshot of [`current_geo_asset_generation`] taken when this instance
+  /// was started (or last respawned by its supervisor), so a later geo asset
+  /// update can be detected as stale.
+  pub geo_asset_generation: i64,
+  pub status: XrayInstanceStatus,
+  pub restart_count: u32,
+}
+
+/// Lifecycle state of a supervised [`XrayInstance`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum XrayInstanceStatus {
+  Starting,
+  Running,
+  Restarting,
+  Failed,
+}
+
+/// Maximum number of consecutive auto-restarts the supervisor attempts
+/// before giving up and marking an instance [`XrayInstanceStatus::Failed`].
+const MAX_RESTART_ATTEMPTS: u32 = 5;
+/// Interval between supervisor liveness checks.
+const SUPERVISOR_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+/// How long an instance must run without exiting before the supervisor
+/// treats earlier crashes as resolved and resets `restart_count`, so an
+/// instance that crashes only occasionally over days doesn't eventually hit
+/// [`MAX_RESTART_ATTEMPTS`] from restarts that each individually recovered.
+const RESTART_COUNT_RESET_AFTER: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Result of a verified Xray install, returned so callers can display which
+/// build was installed and confirm its integrity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XrayDownloadInfo {
+  pub version: String,
+  pub sha256: String,
+}
+
+/// Extracts the `SHA2-256=` hex digest from an XTLS `.dgst` sidecar body,
+/// e.g. a line like `SHA2-256= abcdef...`.
+fn parse_sha256_from_dgst(dgst_body: &str) -> Option<String> {
+  dgst_body.lines().find_map(|line| {
+    let line = line.trim();
+    line
+      .strip_prefix("SHA2-256=")
+      .or_else(|| line.strip_prefix("SHA2-256 ="))
+      .map(|hash| hash.trim().to_lowercase())
+  })
 }
 
 // Global Xray instances registry
 lazy_static::lazy_static! {
   static ref XRAY_INSTANCES: Mutex<std::collections::HashMap<String, XrayInstance>> =
     Mutex::new(std::collections::HashMap::new());
+  // Child handles, kept separately since they aren't Serialize/Clone like XrayInstance.
+  static ref XRAY_CHILDREN: Mutex<std::collections::HashMap<String, std::process::Child>> =
+    Mutex::new(std::collections::HashMap::new());
+  // Per-instance flag the supervisor task polls to know when to stop watching.
+  static ref XRAY_SUPERVISOR_STOP_FLAGS: Mutex<std::collections::HashMap<String, std::sync::Arc<std::sync::atomic::AtomicBool>>> =
+    Mutex::new(std::collections::HashMap::new());
+  // Bounded per-instance stdout/stderr tail, drained by spawn_log_readers.
+  static ref XRAY_LOGS: std::sync::Mutex<std::collections::HashMap<String, std::collections::VecDeque<String>>> =
+    std::sync::Mutex::new(std::collections::HashMap::new());
+}
+
+/// Cap on how much of each instance's recent stdout/stderr is kept in
+/// memory; older lines are dropped once this is exceeded.
+const LOG_RING_BUFFER_MAX_BYTES: usize = 64 * 1024;
+/// Number of trailing log lines surfaced in a startup-timeout error.
+const STARTUP_ERROR_LOG_LINES: usize = 10;
+
+fn append_log_line(id: &str, line: String) {
+  let mut logs = XRAY_LOGS.lock().unwrap();
+  let buffer = logs.entry(id.to_string()).or_default();
+  buffer.push_back(line);
+
+  let mut total_bytes: usize = buffer.iter().map(|l| l.len() + 1).sum();
+  while total_bytes > LOG_RING_BUFFER_MAX_BYTES && buffer.len() > 1 {
+    if let Some(removed) = buffer.pop_front() {
+      total_bytes -= removed.len() + 1;
+    }
+  }
+}
+
+/// Path of the on-disk log file mirroring an instance's captured
+/// stdout/stderr, kept alongside its generated config.
+fn get_instance_log_path(id: &str) -> PathBuf {
+  get_xray_bin_dir()
+    .parent()
+    .unwrap()
+    .join("configs")
+    .join(format!("{}.log", id))
+}
+
+/// Drains `stream` line-by-line on a blocking task, appending each line to
+/// the in-memory ring buffer for `id` and to its on-disk log file.
+fn spawn_log_reader<R>(id: String, stream: R)
+where
+  R: std::io::Read + Send + 'static,
+{
+  tokio::task::spawn_blocking(move || {
+    use std::io::BufRead;
+    let log_path = get_instance_log_path(&id);
+    let mut log_file = std::fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&log_path)
+      .ok();
+
+    let reader = std::io::BufReader::new(stream);
+    for line in reader.lines() {
+      let Ok(line) = line else {
+        break;
+      };
+      if let Some(file) = log_file.as_mut() {
+        let _ = writeln!(file, "{}", line);
+      }
+      append_log_line(&id, line);
+    }
+  });
+}
+
+/// Returns the most recent captured stdout/stderr lines for `id`, oldest
+/// first (empty if the instance hasn't logged anything or doesn't exist).
+pub async fn get_xray_logs(id: &str) -> Vec<String> {
+  XRAY_LOGS
+    .lock()
+    .unwrap()
+    .get(id)
+    .map(|buffer| buffer.iter().cloned().collect())
+    .unwrap_or_default()
+}
+
+fn clear_xray_logs(id: &str) {
+  XRAY_LOGS.lock().unwrap().remove(id);
+  let _ = fs::remove_file(get_instance_log_path(id));
 }
 
 /// Get Xray binary directory
@@ -108,23 +238,123 @@ fn get_xray_asset_name() -> String {
   }
 }
 
-/// Get Xray executable path
+/// How the app locates the Xray binary: either manage its own download under
+/// [`get_xray_bin_dir`], or defer entirely to a distro-packaged install.
+/// Selected via `DONUT_XRAY_STRATEGY=system|download` (default `download`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum XrayStrategy {
+  Download,
+  System,
+}
+
+fn parse_xray_strategy(value: Option<&str>) -> XrayStrategy {
+  match value {
+    Some("system") => XrayStrategy::System,
+    _ => XrayStrategy::Download,
+  }
+}
+
+fn get_xray_strategy() -> XrayStrategy {
+  parse_xray_strategy(std::env::var("DONUT_XRAY_STRATEGY").ok().as_deref())
+}
+
+/// Common locations a packaged `xray` binary is found at when the user
+/// hasn't set `DONUT_XRAY_PATH` explicitly (e.g. NixOS/Arch `services.xray`).
+const COMMON_SYSTEM_XRAY_PATHS: &[&str] = &[
+  "/usr/bin/xray",
+  "/usr/local/bin/xray",
+  "/run/current-system/sw/bin/xray",
+];
+
+/// Resolves the `system` strategy's binary path: `DONUT_XRAY_PATH` if set,
+/// otherwise the first [`COMMON_SYSTEM_XRAY_PATHS`] entry that exists, or
+/// that list's first entry if none do (so the caller gets a sensible path
+/// to report as missing).
+fn get_system_xray_path() -> PathBuf {
+  if let Ok(path) = std::env::var("DONUT_XRAY_PATH") {
+    return PathBuf::from(path);
+  }
+
+  COMMON_SYSTEM_XRAY_PATHS
+    .iter()
+    .map(PathBuf::from)
+    .find(|path| path.exists())
+    .unwrap_or_else(|| PathBuf::from(COMMON_SYSTEM_XRAY_PATHS[0]))
+}
+
+/// Get Xray executable path. Under the `system` strategy this resolves to
+/// [`get_system_xray_path`] instead of the app-managed download directory.
 pub fn get_xray_executable_path() -> PathBuf {
-  get_xray_bin_dir().join(get_xray_executable_name())
+  match get_xray_strategy() {
+    XrayStrategy::System => get_system_xray_path(),
+    XrayStrategy::Download => get_xray_bin_dir().join(get_xray_executable_name()),
+  }
+}
+
+/// Common locations a packaged Xray install publishes `geoip.dat`/
+/// `geosite.dat` to, probed under the `system` strategy when
+/// `XRAY_LOCATION_ASSET` isn't set.
+const COMMON_SYSTEM_GEO_ASSET_DIRS: &[&str] =
+  &["/usr/share/xray", "/usr/local/share/xray", "/usr/share/v2ray"];
+
+/// Directory shared by all Xray instances for `geoip.dat`/`geosite.dat`,
+/// set as `XRAY_LOCATION_ASSET` when spawning an instance. Under the
+/// `system` strategy this honors an already-set `XRAY_LOCATION_ASSET` first,
+/// then probes [`COMMON_SYSTEM_GEO_ASSET_DIRS`], since a packaged Xray won't
+/// have written its geo assets into the app's download directory.
+fn get_geo_asset_dir() -> PathBuf {
+  if get_xray_strategy() == XrayStrategy::System {
+    if let Ok(dir) = std::env::var("XRAY_LOCATION_ASSET") {
+      return PathBuf::from(dir);
+    }
+    return COMMON_SYSTEM_GEO_ASSET_DIRS
+      .iter()
+      .map(PathBuf::from)
+      .find(|path| path.join("geoip.dat").exists())
+      .unwrap_or_else(|| PathBuf::from(COMMON_SYSTEM_GEO_ASSET_DIRS[0]));
+  }
+
+  get_xray_bin_dir().parent().unwrap().to_path_buf()
 }
 
-/// Check if Xray is installed
+/// Check if Xray is installed. Under the `system` strategy this checks the
+/// configured/probed system path rather than the app's download directory.
 pub fn is_xray_installed() -> bool {
   get_xray_executable_path().exists()
 }
 
-/// Get Xray version
+/// Path to the small JSON sidecar recording which version/checksum is
+/// currently installed, written by [`download_xray_version`].
+fn get_installed_metadata_path() -> PathBuf {
+  get_xray_bin_dir().join("installed.json")
+}
+
+fn read_installed_metadata() -> Option<XrayDownloadInfo> {
+  let content = fs::read_to_string(get_installed_metadata_path()).ok()?;
+  serde_json::from_str(&content).ok()
+}
+
+fn write_installed_metadata(info: &XrayDownloadInfo) -> std::io::Result<()> {
+  let content = serde_json::to_string_pretty(info)?;
+  fs::write(get_installed_metadata_path(), content)
+}
+
+/// Get Xray version. Prefers the version recorded by `download_xray`/
+/// `download_xray_version` so this doesn't need to spawn the binary; falls
+/// back to running `xray version` for installs that predate that metadata
+/// file (e.g. a system-provided binary).
 pub async fn get_xray_version() -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
   let exe_path = get_xray_executable_path();
   if !exe_path.exists() {
     return Err("Xray not installed".into());
   }
 
+  if get_xray_strategy() == XrayStrategy::Download {
+    if let Some(metadata) = read_installed_metadata() {
+      return Ok(metadata.version);
+    }
+  }
+
   let output = tokio::process::Command::new(&exe_path)
     .arg("version")
     .output()
@@ -161,18 +391,97 @@ async fn get_latest_xray_version() -> Result<String, Box<dyn std::error::Error +
   Ok(release.tag_name)
 }
 
-/// Download and install Xray
+/// List available Xray versions (release tags) from GitHub, newest first,
+/// so callers can offer explicit upgrade/downgrade instead of always
+/// installing whatever `/releases/latest` currently points to.
+pub async fn list_xray_versions() -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+  let client = reqwest::Client::builder()
+    .user_agent("DonutBrowser")
+    .build()?;
+
+  let response = client
+    .get("https://api.github.com/repos/XTLS/Xray-core/releases")
+    .send()
+    .await?;
+
+  if !response.status().is_success() {
+    return Err(format!("GitHub API error: {}", response.status()).into());
+  }
+
+  let releases: Vec<GitHubRelease> = response.json().await?;
+  Ok(releases.into_iter().map(|r| r.tag_name).collect())
+}
+
+/// Download and install the latest Xray release.
 pub async fn download_xray(
   progress_callback: Option<Box<dyn Fn(u64, u64) + Send + Sync>>,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-  log::info!("Starting Xray download...");
-
-  // Get latest version
+) -> Result<XrayDownloadInfo, Box<dyn std::error::Error + Send + Sync>> {
   let version = get_latest_xray_version().await.unwrap_or_else(|e| {
     log::warn!("Failed to get latest version: {}, using fallback", e);
     "v25.1.1".to_string()
   });
 
+  download_xray_version(&version, progress_callback).await
+}
+
+/// Confirms the binary at [`get_xray_executable_path`] is a working Xray
+/// install by running `xray version`, instead of downloading anything. Used
+/// under the `system` strategy, where the binary is distro-managed.
+async fn validate_system_xray_binary() -> Result<XrayDownloadInfo, Box<dyn std::error::Error + Send + Sync>> {
+  let exe_path = get_xray_executable_path();
+  if !exe_path.exists() {
+    return Err(
+      format!(
+        "No Xray binary found at {} (set DONUT_XRAY_PATH or install one of: {})",
+        exe_path.display(),
+        COMMON_SYSTEM_XRAY_PATHS.join(", ")
+      )
+      .into(),
+    );
+  }
+
+  let output = tokio::process::Command::new(&exe_path)
+    .arg("version")
+    .output()
+    .await?;
+
+  if !output.status.success() {
+    return Err(format!("{} did not respond to `version`", exe_path.display()).into());
+  }
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let version = stdout
+    .lines()
+    .next()
+    .and_then(|line| line.split_whitespace().nth(1))
+    .unwrap_or("unknown")
+    .to_string();
+
+  let sha256 = fs::read(&exe_path)
+    .map(|bytes| format!("{:x}", Sha256::digest(&bytes)))
+    .unwrap_or_default();
+
+  log::info!("Validated system Xray binary at {}: {}", exe_path.display(), version);
+  Ok(XrayDownloadInfo { version, sha256 })
+}
+
+/// Download and install a specific, pinned Xray release, so a known-good
+/// version can be (re)installed or rolled back to without always resolving
+/// `/releases/latest`. Under the `system` strategy this skips downloading
+/// entirely and instead validates the configured binary via
+/// [`validate_system_xray_binary`].
+pub async fn download_xray_version(
+  version: &str,
+  progress_callback: Option<Box<dyn Fn(u64, u64) + Send + Sync>>,
+) -> Result<XrayDownloadInfo, Box<dyn std::error::Error + Send + Sync>> {
+  if get_xray_strategy() == XrayStrategy::System {
+    log::info!(
+      "Xray strategy is 'system', skipping download of {} and validating the configured binary instead",
+      version
+    );
+    return validate_system_xray_binary().await;
+  }
+
   log::info!("Downloading Xray version: {}", version);
 
   let asset_name = get_xray_asset_name();
@@ -180,9 +489,11 @@ pub async fn download_xray(
     "https://github.com/XTLS/Xray-core/releases/download/{}/{}",
     version, asset_name
   );
+  let dgst_url = format!("{}.dgst", download_url);
 
   // Try mirror if direct download fails (for users in restricted regions)
   let mirror_url = format!("https://gh-proxy.com/{}", download_url);
+  let mirror_dgst_url = format!("https://gh-proxy.com/{}", dgst_url);
 
   let client = reqwest::Client::builder()
     .user_agent("DonutBrowser")
@@ -202,6 +513,21 @@ pub async fn download_xray(
     return Err(format!("Download failed: {}", response.status()).into());
   }
 
+  // Fetch the published digest sidecar so the download can be verified
+  // before extraction.
+  let dgst_response = match client.get(&dgst_url).send().await {
+    Ok(r) if r.status().is_success() => r,
+    _ => client.get(&mirror_dgst_url).send().await?,
+  };
+
+  if !dgst_response.status().is_success() {
+    return Err(format!("Failed to fetch checksum file: {}", dgst_response.status()).into());
+  }
+
+  let dgst_body = dgst_response.text().await?;
+  let expected_sha256 = parse_sha256_from_dgst(&dgst_body)
+    .ok_or("Checksum file did not contain a SHA2-256 entry")?;
+
   let total_size = response.content_length().unwrap_or(0);
   let mut downloaded: u64 = 0;
 
@@ -211,6 +537,7 @@ pub async fn download_xray(
 
   let zip_path = bin_dir.join("xray.zip");
   let mut file = fs::File::create(&zip_path)?;
+  let mut hasher = Sha256::new();
 
   let mut stream = response.bytes_stream();
   use futures_util::StreamExt;
@@ -218,6 +545,7 @@ pub async fn download_xray(
   while let Some(chunk) = stream.next().await {
     let chunk = chunk?;
     file.write_all(&chunk)?;
+    hasher.update(&chunk);
     downloaded += chunk.len() as u64;
 
     if let Some(ref callback) = progress_callback {
@@ -227,7 +555,19 @@ pub async fn download_xray(
 
   drop(file);
 
-  log::info!("Download complete, extracting...");
+  let actual_sha256 = format!("{:x}", hasher.finalize());
+  if actual_sha256 != expected_sha256 {
+    let _ = fs::remove_file(&zip_path);
+    return Err(
+      format!(
+        "Checksum mismatch for {}: expected {}, got {}",
+        asset_name, expected_sha256, actual_sha256
+      )
+      .into(),
+    );
+  }
+
+  log::info!("Download complete and checksum verified, extracting...");
 
   // Extract zip
   let zip_file = fs::File::open(&zip_path)?;
@@ -265,7 +605,7 @@ pub async fn download_xray(
 
   // Move geo files to parent directory for sharing
   let geo_files = ["geoip.dat", "geosite.dat"];
-  let parent_dir = bin_dir.parent().unwrap();
+  let parent_dir = get_geo_asset_dir();
   for geo_file in geo_files {
     let src = bin_dir.join(geo_file);
     let dst = parent_dir.join(geo_file);
@@ -277,26 +617,207 @@ pub async fn download_xray(
   }
 
   log::info!("Xray {} installed successfully", version);
-  Ok(version)
+  let info = XrayDownloadInfo {
+    version: version.to_string(),
+    sha256: actual_sha256,
+  };
+  if let Err(e) = write_installed_metadata(&info) {
+    log::warn!("Failed to persist installed Xray metadata: {}", e);
+  }
+  Ok(info)
 }
 
-/// Start Xray instance for a proxy URL
-pub async fn start_xray_instance(
+/// Default repository publishing community-maintained `geoip.dat`/
+/// `geosite.dat` builds, refreshed far more often than Xray itself.
+pub const DEFAULT_GEO_ASSET_REPO: &str = "Loyalsoldier/v2ray-rules-dat";
+
+/// Record of one installed geo asset's verified checksum and install time,
+/// persisted alongside the asset files themselves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeoAssetInfo {
+  pub name: String,
+  pub sha256: String,
+  pub updated_at: i64,
+}
+
+type GeoAssetMetadata = std::collections::HashMap<String, GeoAssetInfo>;
+
+fn get_geo_metadata_path() -> PathBuf {
+  get_geo_asset_dir().join("geo_assets.json")
+}
+
+fn read_geo_metadata() -> GeoAssetMetadata {
+  fs::read_to_string(get_geo_metadata_path())
+    .ok()
+    .and_then(|content| serde_json::from_str(&content).ok())
+    .unwrap_or_default()
+}
+
+fn write_geo_metadata(metadata: &GeoAssetMetadata) -> std::io::Result<()> {
+  let content = serde_json::to_string_pretty(metadata)?;
+  fs::write(get_geo_metadata_path(), content)
+}
+
+/// Extracts the checksum for `asset_name` from a standard `sha256sum`-style
+/// body (`<hex>  <filename>` per line), the format used by the geo asset
+/// repo's release checksum file.
+fn parse_sha256_from_sha256sum(body: &str, asset_name: &str) -> Option<String> {
+  body.lines().find_map(|line| {
+    let mut parts = line.split_whitespace();
+    let hash = parts.next()?;
+    let name = parts.next()?.trim_start_matches('*');
+    if name == asset_name {
+      Some(hash.to_lowercase())
+    } else {
+      None
+    }
+  })
+}
+
+/// Combined generation marker for the currently installed geo assets: the
+/// newest `updated_at` across `geoip.dat`/`geosite.dat`, or `0` if neither
+/// has been downloaded through [`update_geo_asset`] yet. Instances compare
+/// their stored [`XrayInstance::geo_asset_generation`] against this to
+/// detect that a geo asset update happened after they started.
+pub fn current_geo_asset_generation() -> i64 {
+  read_geo_metadata()
+    .values()
+    .map(|info| info.updated_at)
+    .max()
+    .unwrap_or(0)
+}
+
+/// Downloads and verifies one geo asset (`geoip.dat` or `geosite.dat`) from
+/// `repo` (a `owner/name` GitHub repo whose latest release publishes the
+/// asset plus a `<asset>.sha256sum` sidecar), then atomically replaces it in
+/// the shared [`get_geo_asset_dir`] directory used by every Xray instance.
+pub async fn update_geo_asset(
+  asset_name: &str,
+  repo: &str,
+) -> Result<GeoAssetInfo, Box<dyn std::error::Error + Send + Sync>> {
+  let client = reqwest::Client::builder()
+    .user_agent("DonutBrowser")
+    .timeout(std::time::Duration::from_secs(120))
+    .build()?;
+
+  let base_url = format!(
+    "https://github.com/{}/releases/latest/download",
+    repo
+  );
+  let asset_url = format!("{}/{}", base_url, asset_name);
+  let checksum_url = format!("{}/{}.sha256sum", base_url, asset_name);
+
+  let response = client.get(&asset_url).send().await?;
+  if !response.status().is_success() {
+    return Err(format!("Failed to download {}: {}", asset_name, response.status()).into());
+  }
+
+  let checksum_response = client.get(&checksum_url).send().await?;
+  if !checksum_response.status().is_success() {
+    return Err(format!(
+      "Failed to download checksum for {}: {}",
+      asset_name,
+      checksum_response.status()
+    )
+    .into());
+  }
+  let checksum_body = checksum_response.text().await?;
+  let expected_sha256 = parse_sha256_from_sha256sum(&checksum_body, asset_name)
+    .ok_or_else(|| format!("Checksum file did not contain an entry for {}", asset_name))?;
+
+  let asset_dir = get_geo_asset_dir();
+  fs::create_dir_all(&asset_dir)?;
+
+  let tmp_path = asset_dir.join(format!("{}.tmp", asset_name));
+  let mut hasher = Sha256::new();
+  {
+    let mut file = fs::File::create(&tmp_path)?;
+    let mut stream = response.bytes_stream();
+    use futures_util::StreamExt;
+    while let Some(chunk) = stream.next().await {
+      let chunk = chunk?;
+      file.write_all(&chunk)?;
+      hasher.update(&chunk);
+    }
+    file.sync_all()?;
+  }
+
+  let actual_sha256 = format!("{:x}", hasher.finalize());
+  if actual_sha256 != expected_sha256 {
+    let _ = fs::remove_file(&tmp_path);
+    return Err(
+      format!(
+        "Checksum mismatch for {}: expected {}, got {}",
+        asset_name, expected_sha256, actual_sha256
+      )
+      .into(),
+    );
+  }
+
+  let final_path = asset_dir.join(asset_name);
+  fs::rename(&tmp_path, &final_path)?;
+
+  let info = GeoAssetInfo {
+    name: asset_name.to_string(),
+    sha256: actual_sha256,
+    updated_at: std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map(|d| d.as_secs() as i64)
+      .unwrap_or(0),
+  };
+
+  let mut metadata = read_geo_metadata();
+  metadata.insert(asset_name.to_string(), info.clone());
+  write_geo_metadata(&metadata)?;
+
+  Ok(info)
+}
+
+/// Downloads and verifies `geoip.dat` from `repo` (defaulting to
+/// [`DEFAULT_GEO_ASSET_REPO`] when `None`).
+pub async fn update_geoip(repo: Option<&str>) -> Result<GeoAssetInfo, Box<dyn std::error::Error + Send + Sync>> {
+  update_geo_asset("geoip.dat", repo.unwrap_or(DEFAULT_GEO_ASSET_REPO)).await
+}
+
+/// Downloads and verifies `geosite.dat` from `repo` (defaulting to
+/// [`DEFAULT_GEO_ASSET_REPO`] when `None`).
+pub async fn update_geosite(repo: Option<&str>) -> Result<GeoAssetInfo, Box<dyn std::error::Error + Send + Sync>> {
+  update_geo_asset("geosite.dat", repo.unwrap_or(DEFAULT_GEO_ASSET_REPO)).await
+}
+
+/// Lists the ids of running instances that were started before the most
+/// recent geo asset update and so are still serving routing decisions from
+/// stale `geoip.dat`/`geosite.dat` data until restarted.
+pub async fn list_stale_geo_instances() -> Vec<String> {
+  let current_generation = current_geo_asset_generation();
+  let instances = XRAY_INSTANCES.lock().await;
+  instances
+    .values()
+    .filter(|instance| instance.geo_asset_generation < current_generation)
+    .map(|instance| instance.id.clone())
+    .collect()
+}
+
+/// Writes the Xray JSON config for `id` to its config file and returns the
+/// path, so both the initial start and a later supervisor-driven respawn
+/// regenerate the exact same config.
+fn write_instance_config(
   id: &str,
-  upstream_url: &str,
   local_port: u16,
+  upstream_url: &str,
   pre_proxy_url: Option<&str>,
-) -> Result<XrayInstance, Box<dyn std::error::Error + Send + Sync>> {
-  // Check if Xray is installed
-  if !is_xray_installed() {
-    return Err("Xray is not installed. Please download it first.".into());
+) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+  let mut proxy_chain = Vec::new();
+  if let Some(pre_url) = pre_proxy_url {
+    if !pre_url.is_empty() {
+      proxy_chain.push(pre_url);
+    }
   }
+  proxy_chain.push(upstream_url);
 
-  // Generate config
-  let config = generate_xray_config(upstream_url, local_port, pre_proxy_url)
+  let config = generate_xray_config(local_port, &proxy_chain, &[], false, &[])
     .map_err(|e| Box::<dyn std::error::Error + Send + Sync>::from(e))?;
 
-  // Write config to temp file
   let config_dir = get_xray_bin_dir().parent().unwrap().join("configs");
   fs::create_dir_all(&config_dir)?;
 
@@ -304,27 +825,23 @@ pub async fn start_xray_instance(
   let config_content = serde_json::to_string_pretty(&config)?;
   fs::write(&config_path, &config_content)?;
 
-  log::info!(
-    "Starting Xray instance {} on port {} for {}",
-    id,
-    local_port,
-    upstream_url
-  );
+  Ok(config_path)
+}
 
-  // Start Xray process
+/// Spawns the Xray process for `config_path`, detached from this process
+/// group the same way on every platform-specific path so the initial start
+/// and a supervisor respawn behave identically.
+fn spawn_xray_child(config_path: &std::path::Path, geo_dir: &std::path::Path) -> std::io::Result<std::process::Child> {
   let exe_path = get_xray_executable_path();
 
-  // Set environment for geo files
-  let geo_dir = get_xray_bin_dir().parent().unwrap().to_path_buf();
-
   #[cfg(unix)]
-  let child = {
+  {
     use std::os::unix::process::CommandExt;
     let mut cmd = std::process::Command::new(&exe_path);
     cmd.arg("run");
     cmd.arg("-c");
-    cmd.arg(&config_path);
-    cmd.env("XRAY_LOCATION_ASSET", &geo_dir);
+    cmd.arg(config_path);
+    cmd.env("XRAY_LOCATION_ASSET", geo_dir);
     cmd.stdin(Stdio::null());
     cmd.stdout(Stdio::piped());
     cmd.stderr(Stdio::piped());
@@ -336,11 +853,11 @@ pub async fn start_xray_instance(
       });
     }
 
-    cmd.spawn()?
-  };
+    cmd.spawn()
+  }
 
   #[cfg(windows)]
-  let child = {
+  {
     use std::os::windows::process::CommandExt;
     const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
     const CREATE_NO_WINDOW: u32 = 0x08000000;
@@ -348,58 +865,301 @@ pub async fn start_xray_instance(
     std::process::Command::new(&exe_path)
       .arg("run")
       .arg("-c")
-      .arg(&config_path)
-      .env("XRAY_LOCATION_ASSET", &geo_dir)
+      .arg(config_path)
+      .env("XRAY_LOCATION_ASSET", geo_dir)
       .stdin(Stdio::null())
       .stdout(Stdio::piped())
       .stderr(Stdio::piped())
       .creation_flags(CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW)
-      .spawn()?
-  };
+      .spawn()
+  }
+}
+
+/// Polls `127.0.0.1:local_port` until Xray is accepting connections or the
+/// attempt budget is exhausted.
+async fn wait_for_xray_listening(local_port: u16, max_attempts: u32) -> bool {
+  for _ in 0..max_attempts {
+    if tokio::net::TcpStream::connect(("127.0.0.1", local_port))
+      .await
+      .is_ok()
+    {
+      return true;
+    }
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+  }
+  false
+}
+
+/// Watches a running instance and auto-restarts it (regenerating its config
+/// and respawning on the same port) if the Xray process exits unexpectedly,
+/// with exponential backoff and a [`MAX_RESTART_ATTEMPTS`] cap before giving
+/// up and marking the instance [`XrayInstanceStatus::Failed`].
+fn spawn_instance_supervisor(id: String, stop_flag: std::sync::Arc<std::sync::atomic::AtomicBool>) {
+  tokio::spawn(async move {
+    // Tracks how long the current run has been alive, so a sustained run can
+    // clear out restart_count from earlier, unrelated crashes. `None` once
+    // that's already happened for the current run, to avoid re-checking
+    // every poll.
+    let mut running_since = Some(std::time::Instant::now());
+
+    loop {
+      tokio::time::sleep(SUPERVISOR_POLL_INTERVAL).await;
+
+      if stop_flag.load(std::sync::atomic::Ordering::SeqCst) {
+        return;
+      }
+
+      let exited = {
+        let mut children = XRAY_CHILDREN.lock().await;
+        match children.get_mut(&id) {
+          Some(child) => matches!(child.try_wait(), Ok(Some(_))),
+          None => return,
+        }
+      };
+
+      if !exited {
+        if let Some(since) = running_since {
+          if since.elapsed() >= RESTART_COUNT_RESET_AFTER {
+            if let Some(mut instance) = get_xray_instance(&id).await {
+              if instance.restart_count != 0 {
+                log::info!(
+                  "Xray instance {} has run for {:?} without crashing, resetting restart count",
+                  id,
+                  RESTART_COUNT_RESET_AFTER
+                );
+                instance.restart_count = 0;
+                XRAY_INSTANCES.lock().await.insert(id.clone(), instance);
+              }
+            }
+            running_since = None;
+          }
+        }
+        continue;
+      }
+
+      running_since = None;
+
+      let Some(mut instance) = get_xray_instance(&id).await else {
+        return;
+      };
+
+      if instance.restart_count >= MAX_RESTART_ATTEMPTS {
+        log::error!(
+          "Xray instance {} exited and exceeded {} restart attempts, marking failed",
+          id,
+          MAX_RESTART_ATTEMPTS
+        );
+        instance.status = XrayInstanceStatus::Failed;
+        XRAY_INSTANCES.lock().await.insert(id.clone(), instance);
+        return;
+      }
+
+      instance.restart_count += 1;
+      instance.status = XrayInstanceStatus::Restarting;
+      XRAY_INSTANCES.lock().await.insert(id.clone(), instance.clone());
+
+      let backoff = std::time::Duration::from_secs(2u64.saturating_pow(instance.restart_count));
+      log::warn!(
+        "Xray instance {} exited unexpectedly, restarting in {:?} (attempt {}/{})",
+        id,
+        backoff,
+        instance.restart_count,
+        MAX_RESTART_ATTEMPTS
+      );
+      tokio::time::sleep(backoff).await;
+
+      // The user may have stopped the instance (or it may have been torn
+      // down some other way) while we were sleeping off the backoff; don't
+      // resurrect it as an untracked zombie if so.
+      if stop_flag.load(std::sync::atomic::Ordering::SeqCst) {
+        log::info!("Xray instance {} was stopped during restart backoff, aborting respawn", id);
+        return;
+      }
+      if get_xray_instance(&id).await.is_none() || !XRAY_CHILDREN.lock().await.contains_key(&id) {
+        log::info!("Xray instance {} no longer exists, aborting respawn", id);
+        return;
+      }
+
+      let config_path = match write_instance_config(
+        &id,
+        instance.local_port,
+        &instance.upstream_url,
+        instance.pre_proxy_url.as_deref(),
+      ) {
+        Ok(path) => path,
+        Err(e) => {
+          log::error!("Failed to regenerate config for Xray instance {}: {}", id, e);
+          continue;
+        }
+      };
+
+      let geo_dir = get_geo_asset_dir();
+      let mut child = match spawn_xray_child(&config_path, &geo_dir) {
+        Ok(child) => child,
+        Err(e) => {
+          log::error!("Failed to respawn Xray instance {}: {}", id, e);
+          continue;
+        }
+      };
+
+      if let Some(stdout) = child.stdout.take() {
+        spawn_log_reader(id.clone(), stdout);
+      }
+      if let Some(stderr) = child.stderr.take() {
+        spawn_log_reader(id.clone(), stderr);
+      }
 
+      instance.pid = child.id();
+      instance.config_path = config_path;
+      instance.geo_asset_generation = current_geo_asset_generation();
+
+      {
+        let mut children = XRAY_CHILDREN.lock().await;
+        // Re-check under the same lock `stop_xray_instance` removes entries
+        // under, so a stop that lands after the recheck above (but before
+        // this insert) can't be lost: if it fired while we were spawning,
+        // kill the process we just started instead of registering it and
+        // resurrecting an instance the user stopped.
+        if stop_flag.load(std::sync::atomic::Ordering::SeqCst) {
+          log::info!(
+            "Xray instance {} was stopped while respawning, killing the new process and aborting",
+            id
+          );
+          let _ = child.kill();
+          return;
+        }
+        children.insert(id.clone(), child);
+      }
+
+      let started = wait_for_xray_listening(instance.local_port, 20).await;
+      instance.status = if started {
+        XrayInstanceStatus::Running
+      } else {
+        XrayInstanceStatus::Restarting
+      };
+      XRAY_INSTANCES.lock().await.insert(id.clone(), instance);
+
+      running_since = if started {
+        Some(std::time::Instant::now())
+      } else {
+        None
+      };
+    }
+  });
+}
+
+/// Start Xray instance for a proxy URL
+pub async fn start_xray_instance(
+  id: &str,
+  upstream_url: &str,
+  local_port: u16,
+  pre_proxy_url: Option<&str>,
+) -> Result<XrayInstance, Box<dyn std::error::Error + Send + Sync>> {
+  // Check if Xray is installed
+  if !is_xray_installed() {
+    return Err("Xray is not installed. Please download it first.".into());
+  }
+
+  let config_path = write_instance_config(id, local_port, upstream_url, pre_proxy_url)?;
+
+  log::info!(
+    "Starting Xray instance {} on port {} for {}",
+    id,
+    local_port,
+    upstream_url
+  );
+
+  let geo_dir = get_geo_asset_dir();
+  let mut child = spawn_xray_child(&config_path, &geo_dir)?;
   let pid = child.id();
 
-  let instance = XrayInstance {
+  if let Some(stdout) = child.stdout.take() {
+    spawn_log_reader(id.to_string(), stdout);
+  }
+  if let Some(stderr) = child.stderr.take() {
+    spawn_log_reader(id.to_string(), stderr);
+  }
+
+  let mut instance = XrayInstance {
     id: id.to_string(),
     pid,
     local_port,
     upstream_url: upstream_url.to_string(),
+    pre_proxy_url: pre_proxy_url.filter(|url| !url.is_empty()).map(String::from),
     config_path: config_path.clone(),
+    geo_asset_generation: current_geo_asset_generation(),
+    status: XrayInstanceStatus::Starting,
+    restart_count: 0,
   };
 
-  // Store instance
+  // Store instance and child handle
   {
     let mut instances = XRAY_INSTANCES.lock().await;
     instances.insert(id.to_string(), instance.clone());
   }
+  {
+    let mut children = XRAY_CHILDREN.lock().await;
+    children.insert(id.to_string(), child);
+  }
 
   // Wait a moment for Xray to start
   tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
 
-  // Verify it's listening
-  let mut attempts = 0;
-  let max_attempts = 20;
-
-  while attempts < max_attempts {
-    match tokio::net::TcpStream::connect(("127.0.0.1", local_port)).await {
-      Ok(_) => {
-        log::info!("Xray instance {} started successfully on port {}", id, local_port);
-        return Ok(instance);
-      }
-      Err(_) => {
-        attempts += 1;
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-      }
-    }
+  if wait_for_xray_listening(local_port, 20).await {
+    log::info!("Xray instance {} started successfully on port {}", id, local_port);
+    instance.status = XrayInstanceStatus::Running;
+    XRAY_INSTANCES
+      .lock()
+      .await
+      .insert(id.to_string(), instance.clone());
+
+    let stop_flag = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    XRAY_SUPERVISOR_STOP_FLAGS
+      .lock()
+      .await
+      .insert(id.to_string(), stop_flag.clone());
+    spawn_instance_supervisor(id.to_string(), stop_flag);
+
+    return Ok(instance);
   }
 
-  // If we got here, Xray failed to start
+  // If we got here, Xray failed to start. Grab its recent log output before
+  // stop_xray_instance tears down the ring buffer, so the error is
+  // diagnosable instead of just "failed to start listening".
+  let recent_logs = get_xray_logs(id).await;
   stop_xray_instance(id).await?;
-  Err(format!("Xray failed to start listening on port {}", local_port).into())
+
+  let log_tail = recent_logs
+    .iter()
+    .rev()
+    .take(STARTUP_ERROR_LOG_LINES)
+    .rev()
+    .cloned()
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  if log_tail.is_empty() {
+    Err(format!("Xray failed to start listening on port {}", local_port).into())
+  } else {
+    Err(
+      format!(
+        "Xray failed to start listening on port {}. Recent log output:\n{}",
+        local_port, log_tail
+      )
+      .into(),
+    )
+  }
 }
 
 /// Stop Xray instance
 pub async fn stop_xray_instance(id: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+  // Tell the supervisor to stop watching before we kill the process, so it
+  // doesn't race to "restart" an instance we're intentionally stopping.
+  if let Some(stop_flag) = XRAY_SUPERVISOR_STOP_FLAGS.lock().await.remove(id) {
+    stop_flag.store(true, std::sync::atomic::Ordering::SeqCst);
+  }
+  XRAY_CHILDREN.lock().await.remove(id);
+
   let instance = {
     let mut instances = XRAY_INSTANCES.lock().await;
     instances.remove(id)
@@ -428,6 +1188,7 @@ pub async fn stop_xray_instance(id: &str) -> Result<bool, Box<dyn std::error::Er
 
     // Remove config file
     let _ = fs::remove_file(&instance.config_path);
+    clear_xray_logs(id);
 
     Ok(true)
   } else {
@@ -466,6 +1227,265 @@ pub fn requires_xray(upstream_url: &str) -> bool {
   is_xray_protocol(upstream_url)
 }
 
+/// One URL to probe during a benchmark run, how many times to repeat it,
+/// and roughly how much data the response should contain. A response shorter
+/// than `expected_bytes` is logged as a warning; its sample is still counted
+/// in the results.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BenchmarkTarget {
+  pub url: String,
+  pub expected_bytes: u64,
+  pub iterations: u32,
+}
+
+/// A named, user-editable set of [`BenchmarkTarget`]s, loaded from a JSON
+/// workload file so benchmark profiles aren't hardcoded into the app. e.g.:
+/// ```json
+/// {
+///   "name": "quick-connectivity",
+///   "targets": [
+///     { "url": "http://speed.example.com/1mb.bin", "expected_bytes": 1000000, "iterations": 3 }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BenchmarkWorkload {
+  pub name: String,
+  pub targets: Vec<BenchmarkTarget>,
+}
+
+/// Loads a [`BenchmarkWorkload`] from `path`.
+pub fn load_benchmark_workload(
+  path: &std::path::Path,
+) -> Result<BenchmarkWorkload, Box<dyn std::error::Error + Send + Sync>> {
+  let content = fs::read_to_string(path)?;
+  Ok(serde_json::from_str(&content)?)
+}
+
+/// Timings from one iteration of probing a single [`BenchmarkTarget`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkSample {
+  pub tcp_connect_ms: u64,
+  pub ttfb_ms: u64,
+  pub throughput_kbps: f64,
+}
+
+/// Min/median/p95 across a target's [`BenchmarkSample`]s.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PercentileSummary {
+  pub min: f64,
+  pub median: f64,
+  pub p95: f64,
+}
+
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+  let idx = ((sorted.len() - 1) as f64 * fraction).round() as usize;
+  sorted[idx]
+}
+
+fn percentile_summary(mut values: Vec<f64>) -> PercentileSummary {
+  if values.is_empty() {
+    return PercentileSummary {
+      min: 0.0,
+      median: 0.0,
+      p95: 0.0,
+    };
+  }
+  values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+  PercentileSummary {
+    min: values[0],
+    median: percentile(&values, 0.5),
+    p95: percentile(&values, 0.95),
+  }
+}
+
+/// Benchmark results for one [`BenchmarkTarget`]: the raw per-iteration
+/// samples that succeeded, a percentile summary of each metric, and any
+/// iteration errors (e.g. a timeout) so a flaky target doesn't just vanish
+/// from the report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkTargetResult {
+  pub url: String,
+  pub samples: Vec<BenchmarkSample>,
+  pub tcp_connect_ms: PercentileSummary,
+  pub ttfb_ms: PercentileSummary,
+  pub throughput_kbps: PercentileSummary,
+  pub errors: Vec<String>,
+}
+
+/// Full report for one [`benchmark_xray_instance`] run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+  pub instance_id: String,
+  pub workload: String,
+  pub targets: Vec<BenchmarkTargetResult>,
+}
+
+/// Builds the SOCKS5 CONNECT request body for `host:port`, using a
+/// domain-name address (type `0x03`) since that's what every inbound
+/// accepts regardless of whether `host` is itself an IP literal.
+fn build_socks5_connect_request(host: &str, port: u16) -> Vec<u8> {
+  let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+  request.extend_from_slice(host.as_bytes());
+  request.extend_from_slice(&port.to_be_bytes());
+  request
+}
+
+/// Speaks just enough SOCKS5 against the instance's local inbound to time
+/// how long it takes Xray to establish the real TCP connection to
+/// `host:port` — a truer signal than [`wait_for_xray_listening`], which only
+/// confirms the local listener itself is up.
+async fn socks5_connect_timed(
+  local_port: u16,
+  host: &str,
+  port: u16,
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+  let start = std::time::Instant::now();
+  let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", local_port)).await?;
+
+  // Greeting: SOCKS5, one auth method offered, "no auth".
+  stream.write_all(&[0x05, 0x01, 0x00]).await?;
+  let mut greeting_reply = [0u8; 2];
+  stream.read_exact(&mut greeting_reply).await?;
+  if greeting_reply != [0x05, 0x00] {
+    return Err("SOCKS5 proxy rejected the no-auth handshake".into());
+  }
+
+  stream
+    .write_all(&build_socks5_connect_request(host, port))
+    .await?;
+
+  // Reply header: VER REP RSV ATYP, followed by a variable-length bound
+  // address we don't need but must still read off the wire.
+  let mut reply_header = [0u8; 4];
+  stream.read_exact(&mut reply_header).await?;
+  if reply_header[1] != 0x00 {
+    return Err(format!("SOCKS5 CONNECT failed with reply code {}", reply_header[1]).into());
+  }
+  let addr_len = match reply_header[3] {
+    0x01 => 4,
+    0x04 => 16,
+    0x03 => {
+      let mut len_byte = [0u8; 1];
+      stream.read_exact(&mut len_byte).await?;
+      len_byte[0] as usize
+    }
+    other => return Err(format!("Unsupported SOCKS5 address type {}", other).into()),
+  };
+  let mut bound_addr = vec![0u8; addr_len + 2];
+  stream.read_exact(&mut bound_addr).await?;
+
+  Ok(start.elapsed().as_millis() as u64)
+}
+
+/// Runs one iteration against `target` through `local_port`: a raw SOCKS5
+/// CONNECT for [`socks5_connect_timed`], then a real request through the
+/// same inbound (via `reqwest`'s SOCKS5 proxy support) to measure
+/// time-to-first-byte and sustained download throughput.
+async fn probe_target_once(
+  local_port: u16,
+  target: &BenchmarkTarget,
+) -> Result<BenchmarkSample, Box<dyn std::error::Error + Send + Sync>> {
+  let url = url::Url::parse(&target.url)?;
+  let host = url.host_str().ok_or("Benchmark target URL missing host")?;
+  let port = url
+    .port_or_known_default()
+    .ok_or("Benchmark target URL missing port")?;
+
+  let tcp_connect_ms = socks5_connect_timed(local_port, host, port).await?;
+
+  let proxy = reqwest::Proxy::all(format!("socks5h://127.0.0.1:{}", local_port))?;
+  let client = reqwest::Client::builder()
+    .proxy(proxy)
+    .timeout(std::time::Duration::from_secs(60))
+    .build()?;
+
+  let request_start = std::time::Instant::now();
+  let mut response = client.get(&target.url).send().await?;
+
+  let mut total_bytes: u64 = 0;
+  let mut ttfb_ms = None;
+  let download_start = std::time::Instant::now();
+
+  while let Some(chunk) = response.chunk().await? {
+    if ttfb_ms.is_none() {
+      ttfb_ms = Some(request_start.elapsed().as_millis() as u64);
+    }
+    total_bytes += chunk.len() as u64;
+  }
+
+  let ttfb_ms = ttfb_ms.unwrap_or_else(|| request_start.elapsed().as_millis() as u64);
+  let elapsed_secs = download_start.elapsed().as_secs_f64().max(0.001);
+  let throughput_kbps = (total_bytes as f64 * 8.0 / 1000.0) / elapsed_secs;
+
+  if target.expected_bytes > 0 && total_bytes < target.expected_bytes {
+    log::warn!(
+      "Benchmark target {} returned {} bytes, expected at least {}",
+      target.url,
+      total_bytes,
+      target.expected_bytes
+    );
+  }
+
+  Ok(BenchmarkSample {
+    tcp_connect_ms,
+    ttfb_ms,
+    throughput_kbps,
+  })
+}
+
+/// Drives real requests for every target in `workload` through `id`'s local
+/// SOCKS inbound (`127.0.0.1:local_port`), repeating each
+/// [`BenchmarkTarget::iterations`] times and reporting min/median/p95 TCP
+/// connect time, time-to-first-byte, and sustained download throughput per
+/// target. Backs a "test proxy" action and lets candidate upstreams be
+/// compared on more than the bare liveness check [`wait_for_xray_listening`]
+/// already does.
+pub async fn benchmark_xray_instance(
+  id: &str,
+  workload: &BenchmarkWorkload,
+) -> Result<BenchmarkResult, Box<dyn std::error::Error + Send + Sync>> {
+  let instance = get_xray_instance(id)
+    .await
+    .ok_or_else(|| format!("Xray instance {} not found", id))?;
+
+  let mut target_results = Vec::with_capacity(workload.targets.len());
+  for target in &workload.targets {
+    let mut samples = Vec::new();
+    let mut errors = Vec::new();
+
+    for _ in 0..target.iterations.max(1) {
+      match probe_target_once(instance.local_port, target).await {
+        Ok(sample) => samples.push(sample),
+        Err(e) => errors.push(e.to_string()),
+      }
+    }
+
+    let tcp_connect_ms =
+      percentile_summary(samples.iter().map(|s| s.tcp_connect_ms as f64).collect());
+    let ttfb_ms = percentile_summary(samples.iter().map(|s| s.ttfb_ms as f64).collect());
+    let throughput_kbps =
+      percentile_summary(samples.iter().map(|s| s.throughput_kbps).collect());
+
+    target_results.push(BenchmarkTargetResult {
+      url: target.url.clone(),
+      samples,
+      tcp_connect_ms,
+      ttfb_ms,
+      throughput_kbps,
+      errors,
+    });
+  }
+
+  Ok(BenchmarkResult {
+    instance_id: id.to_string(),
+    workload: workload.name.clone(),
+    targets: target_results,
+  })
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -485,6 +1505,102 @@ mod tests {
     assert!(asset.ends_with(".zip"));
   }
 
+  #[test]
+  fn test_parse_sha256_from_dgst() {
+    let body = "SHA2-256= abc123def456\nSHA2-512= deadbeef\n";
+    assert_eq!(
+      parse_sha256_from_dgst(body),
+      Some("abc123def456".to_string())
+    );
+  }
+
+  #[test]
+  fn test_parse_sha256_from_dgst_missing_entry() {
+    let body = "SHA2-512= deadbeef\n";
+    assert_eq!(parse_sha256_from_dgst(body), None);
+  }
+
+  #[test]
+  fn test_installed_metadata_json_roundtrip() {
+    let info = XrayDownloadInfo {
+      version: "v25.1.1".to_string(),
+      sha256: "abc123".to_string(),
+    };
+    let json = serde_json::to_string(&info).unwrap();
+    let parsed: XrayDownloadInfo = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.version, info.version);
+    assert_eq!(parsed.sha256, info.sha256);
+  }
+
+  #[test]
+  fn test_parse_sha256_from_sha256sum() {
+    let body = "aaaa1111  geoip.dat\nbbbb2222  geosite.dat\n";
+    assert_eq!(
+      parse_sha256_from_sha256sum(body, "geoip.dat"),
+      Some("aaaa1111".to_string())
+    );
+    assert_eq!(
+      parse_sha256_from_sha256sum(body, "geosite.dat"),
+      Some("bbbb2222".to_string())
+    );
+    assert_eq!(parse_sha256_from_sha256sum(body, "missing.dat"), None);
+  }
+
+  #[test]
+  fn test_current_geo_asset_generation_is_max_of_installed_assets() {
+    let mut metadata = GeoAssetMetadata::new();
+    metadata.insert(
+      "geoip.dat".to_string(),
+      GeoAssetInfo {
+        name: "geoip.dat".to_string(),
+        sha256: "aaaa".to_string(),
+        updated_at: 100,
+      },
+    );
+    metadata.insert(
+      "geosite.dat".to_string(),
+      GeoAssetInfo {
+        name: "geosite.dat".to_string(),
+        sha256: "bbbb".to_string(),
+        updated_at: 200,
+      },
+    );
+    let generation = metadata.values().map(|info| info.updated_at).max().unwrap_or(0);
+    assert_eq!(generation, 200);
+  }
+
+  #[test]
+  fn test_xray_instance_status_roundtrips_through_json() {
+    for status in [
+      XrayInstanceStatus::Starting,
+      XrayInstanceStatus::Running,
+      XrayInstanceStatus::Restarting,
+      XrayInstanceStatus::Failed,
+    ] {
+      let json = serde_json::to_string(&status).unwrap();
+      let parsed: XrayInstanceStatus = serde_json::from_str(&json).unwrap();
+      assert_eq!(parsed, status);
+    }
+  }
+
+  #[test]
+  fn test_log_ring_buffer_keeps_recent_lines_and_evicts_old_ones() {
+    let id = "test-log-ring-buffer-instance";
+    clear_xray_logs(id);
+
+    let big_line = "x".repeat(LOG_RING_BUFFER_MAX_BYTES / 2);
+    append_log_line(id, big_line.clone());
+    append_log_line(id, big_line.clone());
+    append_log_line(id, "newest".to_string());
+
+    let logs = XRAY_LOGS.lock().unwrap().get(id).cloned().unwrap_or_default();
+    let total_bytes: usize = logs.iter().map(|l| l.len() + 1).sum();
+    assert!(total_bytes <= LOG_RING_BUFFER_MAX_BYTES);
+    assert_eq!(logs.back().cloned(), Some("newest".to_string()));
+
+    clear_xray_logs(id);
+  }
+
   #[test]
   fn test_requires_xray() {
     assert!(requires_xray("vmess://abc123"));
@@ -495,4 +1611,61 @@ mod tests {
     assert!(!requires_xray("socks5://localhost:1080"));
     assert!(!requires_xray("DIRECT"));
   }
+
+  #[test]
+  fn test_parse_xray_strategy() {
+    assert_eq!(parse_xray_strategy(Some("system")), XrayStrategy::System);
+    assert_eq!(parse_xray_strategy(Some("download")), XrayStrategy::Download);
+    assert_eq!(parse_xray_strategy(None), XrayStrategy::Download);
+    assert_eq!(parse_xray_strategy(Some("bogus")), XrayStrategy::Download);
+  }
+
+  #[test]
+  fn test_build_socks5_connect_request() {
+    let request = build_socks5_connect_request("example.com", 80);
+    assert_eq!(&request[0..4], &[0x05, 0x01, 0x00, 0x03]);
+    assert_eq!(request[4], "example.com".len() as u8);
+    assert_eq!(&request[5..16], b"example.com");
+    assert_eq!(&request[16..18], &80u16.to_be_bytes());
+  }
+
+  #[test]
+  fn test_percentile_summary() {
+    let summary = percentile_summary(vec![10.0, 20.0, 30.0, 40.0, 50.0]);
+    assert_eq!(summary.min, 10.0);
+    assert_eq!(summary.median, 30.0);
+    assert_eq!(summary.p95, 50.0);
+  }
+
+  #[test]
+  fn test_percentile_summary_empty() {
+    let summary = percentile_summary(vec![]);
+    assert_eq!(summary.min, 0.0);
+    assert_eq!(summary.median, 0.0);
+    assert_eq!(summary.p95, 0.0);
+  }
+
+  #[test]
+  fn test_load_benchmark_workload_roundtrip() {
+    let path = std::env::temp_dir().join(format!(
+      "donut-benchmark-workload-test-{}.json",
+      std::process::id()
+    ));
+    let workload = BenchmarkWorkload {
+      name: "quick-connectivity".to_string(),
+      targets: vec![BenchmarkTarget {
+        url: "http://example.com/1mb.bin".to_string(),
+        expected_bytes: 1_000_000,
+        iterations: 3,
+      }],
+    };
+    fs::write(&path, serde_json::to_string(&workload).unwrap()).unwrap();
+
+    let loaded = load_benchmark_workload(&path).unwrap();
+    assert_eq!(loaded.name, "quick-connectivity");
+    assert_eq!(loaded.targets.len(), 1);
+    assert_eq!(loaded.targets[0].iterations, 3);
+
+    let _ = fs::remove_file(&path);
+  }
 }